@@ -1,40 +1,26 @@
 use std::error::Error;
-use rand::{thread_rng, seq::SliceRandom};
+use std::fmt;
+use std::time::Duration;
 use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
 use termion::color;
 
-use crate::midi::midi_connect;
+use crate::midi::{midi_connect, NoteInput};
+use crate::midi_out::NoteOutput;
+use crate::transitions::voicing_of;
+use crate::repetition::Deck;
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
 use crate::utils::{
-    music::{get_note_name, get_octave},
-    mutex::{KEYS_DOWN, LAST_KEY_PRESS},
+    music::{get_note_name, get_octave, note_index, pitch_class},
     types::{Hand, ChordType, Chord},
-    constants::{DEBOUNCE_MILLIS, NOTE_NAMES},
+    constants::NOTE_NAMES,
 };
 
-pub fn practice_chords_launcher() -> Result<(), Box<dyn Error>> {
-    let chord_type_selections = &[
-        ("Major", ChordType::Major),
-        ("Minor", ChordType::Minor),
-        ("Diminished", ChordType::Diminished),
-        ("Major Seventh", ChordType::MajorSeventh),
-        ("Minor Seventh", ChordType::MinorSeventh),
-        ("Dominant Seventh", ChordType::DominantSeventh),
-        ("Augmented", ChordType::Augmented),
-        ("sus2", ChordType::SusTwo),
-        ("sus4", ChordType::SusFour),
-        ("7sus2", ChordType::SevenSusTwo),
-        ("7sus4", ChordType::SevenSusFour),
-        ("sus6", ChordType::SusSix )];
-
-    let chord_variants: Vec<&str>  = chord_type_selections.iter().map(|x| x.0).collect();
-
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("Pick a chord variant")
-        .items(chord_variants.as_slice())
-        .interact()
-        .unwrap();
+const SLOW_CHORD_MILLIS: u64 = 3000;
+const PROMPT_CHORD_MILLIS: u64 = 1000;
 
-    let chord_type = chord_type_selections[selection].1;
+pub fn practice_chords_launcher() -> Result<(), Box<dyn Error>> {
+    let chord_type = select_chord_type("Pick a chord variant");
 
     let inversion_selections = [0, 1, 2];
 
@@ -44,93 +30,213 @@ pub fn practice_chords_launcher() -> Result<(), Box<dyn Error>> {
         .interact()
         .unwrap();
 
+    let hand = select_hand();
+
+    match midi_connect() {
+        Err(e) => Err(e),
+        Ok(mut conn_in) => {
+            let session = Session::new("chords", &[
+                ("chord_type", format!("{:?}", chord_type)),
+                ("inversion", inversion.to_string()),
+                ("hand", format!("{:?}", hand)),
+            ]);
+            let result = practice_chords(&mut conn_in, session, |deck| generate_chord_list(chord_type, inversion, hand, deck));
+            conn_in.close();
+            result
+        }
+    }
+}
+
+pub const CHORD_TYPES: &'static [(&'static str, ChordType)] = &[
+    ("Major", ChordType::Major),
+    ("Minor", ChordType::Minor),
+    ("Diminished", ChordType::Diminished),
+    ("Major Seventh", ChordType::MajorSeventh),
+    ("Minor Seventh", ChordType::MinorSeventh),
+    ("Dominant Seventh", ChordType::DominantSeventh),
+    ("Augmented", ChordType::Augmented),
+    ("sus2", ChordType::SusTwo),
+    ("sus4", ChordType::SusFour),
+    ("7sus2", ChordType::SevenSusTwo),
+    ("7sus4", ChordType::SevenSusFour),
+    ("sus6", ChordType::SusSix )];
+
+pub fn select_chord_type(prompt: &str) -> ChordType {
+    let chord_variants: Vec<&str>  = CHORD_TYPES.iter().map(|x| x.0).collect();
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt(prompt)
+        .items(chord_variants.as_slice())
+        .interact()
+        .unwrap();
+
+    CHORD_TYPES[selection].1
+}
+
+pub fn select_hand() -> Hand {
     let hand_selection = Select::with_theme(&ColorfulTheme::default())
         .with_prompt("Which hand?")
         .items(&["left", "right", "both"])
         .interact()
         .unwrap();
 
-    let hand = match hand_selection {
+    match hand_selection {
         0 => Hand::Left,
         1 => Hand::Right,
         _ => Hand::Both,
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Answer {
+    Menu,
+    Keyboard,
+}
+
+pub fn select_answer() -> Answer {
+    let answer_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("How would you like to answer?")
+        .items(&["pick from a menu", "play it back on the keyboard"])
+        .default(0)
+        .interact()
+        .unwrap();
+
+    match answer_selection {
+        0 => Answer::Menu,
+        _ => Answer::Keyboard,
+    }
+}
+
+pub fn chord_key(chord: &Chord, hand: Hand) -> String {
+    format!("chord:{}:{:?}:{}:{:?}", chord.root, chord.chord_type, chord.inversion, hand)
+}
+
+// where to sound or light up a chord for this hand, from middle C or the octave below
+pub fn chord_keys(chord: &Chord, hand: Hand) -> Vec<u8> {
+    let base: i32 = match hand {
+        Hand::Left => 48,
+        _ => 60,
     };
 
-    match midi_connect() {
-        Err(e) => Err(e),
-        Ok(conn_in) => {
-            let result = practice_chords(chord_type, inversion, hand);
-            conn_in.close();
-            result
+    voicing_of(&chord.root, chord.chord_type, chord.inversion).iter().map(|x| (base + x) as u8).collect()
+}
+
+pub struct ChordPrompt {
+    pub chord: Chord,
+    pub hand: Hand,
+    pub numeral: Option<String>,
+    pub any_inversion: bool,
+    pub key: String,
+}
+
+impl ChordPrompt {
+    pub fn new(chord: Chord, hand: Hand) -> ChordPrompt {
+        let key = chord_key(&chord, hand);
+
+        ChordPrompt {
+            chord,
+            hand,
+            numeral: None,
+            any_inversion: false,
+            key,
+        }
+    }
+
+    pub fn matches(&self, played: &Chord) -> bool {
+        if self.any_inversion {
+            let played = Chord{root: played.root.clone(), chord_type: played.chord_type, inversion: self.chord.inversion, octave: played.octave};
+            chord_matches(&self.chord, self.hand, &played)
+        }
+        else {
+            chord_matches(&self.chord, self.hand, played)
         }
     }
 }
 
-fn generate_chord_list(chord_type: ChordType, inversion: usize, hand: Hand) -> Vec<(Chord, Hand)> {
-    let mut rng = thread_rng();
-    let mut chords: Vec<(Chord, Hand)> = vec!();
+impl fmt::Display for ChordPrompt {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.numeral {
+            Some(i) => write!(f, "{} ({}), {}", i, self.chord, self.hand),
+            None => write!(f, "{}, {}", self.chord, self.hand),
+        }
+    }
+}
+
+fn generate_chord_list(chord_type: ChordType, inversion: usize, hand: Hand, deck: &Deck) -> Vec<ChordPrompt> {
+    let mut chords: Vec<ChordPrompt> = vec!();
 
     if hand == Hand::Left || hand == Hand::Both {
-        let chords_to_add: Vec<(Chord, Hand)> = NOTE_NAMES.iter().map(|x| (Chord{root: x[0].to_string(), chord_type, inversion, octave: None}, Hand::Left)).collect();
+        let chords_to_add: Vec<ChordPrompt> = NOTE_NAMES.iter().map(|x| ChordPrompt::new(Chord{root: x[0].to_string(), chord_type, inversion, octave: None}, Hand::Left)).collect();
         chords.extend(chords_to_add);
 
     }
     if hand == Hand::Right || hand == Hand::Both {
-        let chords_to_add: Vec<(Chord, Hand)> = NOTE_NAMES.iter().map(|x| (Chord{root: x[0].to_string(), chord_type, inversion, octave: None}, Hand::Right)).collect();
+        let chords_to_add: Vec<ChordPrompt> = NOTE_NAMES.iter().map(|x| ChordPrompt::new(Chord{root: x[0].to_string(), chord_type, inversion, octave: None}, Hand::Right)).collect();
         chords.extend(chords_to_add);
     }
 
-    chords.shuffle(&mut rng);
+    deck.sort(&mut chords, |x| x.key.clone());
     chords
 }
 
-fn practice_chords(chord_type: ChordType, inversion: usize, hand: Hand) -> Result<(), Box<dyn Error>> {
+// a wrong chord is often just the last one still ringing under the pedal
+fn pedal_hint(input: &NoteInput) -> String {
+    let pedals = input.pedals();
+
+    match pedals.sustain || pedals.sostenuto || pedals.soft {
+        true => format!(" (pedals down: {})", pedals),
+        false => String::new(),
+    }
+}
+
+// `generate` is called afresh for every round, so it can reorder prompts as the deck changes
+pub fn practice_chords<F>(input: &mut NoteInput, mut session: Session, generate: F) -> Result<(), Box<dyn Error>> where F: Fn(&Deck) -> Vec<ChordPrompt> {
+    let mut deck = Deck::load()?;
+    let mut output = NoteOutput::open()?;
     let mut replay = true;
 
     while replay {
-        let mut chords = generate_chord_list(chord_type, inversion, hand);
-
-
-        println!("Play {}, {}", chords[0].0, chords[0].1);
-
-        while chords.len() > 0 {
-            let last_key_press = *LAST_KEY_PRESS.lock().unwrap();
-
-            match last_key_press {
-                Some(i) => {
-                    if i.elapsed().as_millis() > DEBOUNCE_MILLIS.into() {
-                        *LAST_KEY_PRESS.lock().unwrap() = None;
-                        if KEYS_DOWN.lock().unwrap().len() > 0 {
-                            match identify_chord() {
-                                Some(i) => {
-                                    let octave_match = match i.octave {
-                                        Some(j) => chords[0].1.value().contains(&j),
-                                        None => true,
-                                    };
-
-                                    if chords[0].0 == i && octave_match {
-                                        println!("{}Correct!{}", color::Fg(color::Green), color::Fg(color::Reset));
-                                        chords.remove(0);
-
-                                        if chords.len() > 0 {
-                                            println!("Play {}, {}", chords[0].0, chords[0].1);
-                                        }
-                                    }
-                                    else {
-                                        println!("{}Try again: {}, {}{}", color::Fg(color::Red), chords[0].0, chords[0].1, color::Fg(color::Reset));
-                                    }
-                                },
-                                None => {
-                                    println!("{}unrecognised chord\nTry again: {}, {}{}", color::Fg(color::Red), chords[0].0, chords[0].1, color::Fg(color::Reset));
-                                }
-                            }
+        let prompts = generate(&deck);
+        let mut round = Round::default();
+
+        for prompt in prompts.iter() {
+            println!("Play {}", prompt);
+            let mut attempt = Attempt::new(prompt.to_string());
+
+            if let Some(output) = output.as_mut() {
+                output.play_chord(&chord_keys(&prompt.chord, prompt.hand), PROMPT_CHORD_MILLIS);
+            }
+
+            loop {
+                let (keys, pressed) = input.next_state()?;
+
+                match identify_chord(&keys) {
+                    Some(i) => {
+                        if prompt.matches(&i) {
+                            println!("{}Correct!{}", color::Fg(color::Green), color::Fg(color::Reset));
+                            attempt.finish(pressed);
+                            break;
                         }
+                        else {
+                            attempt.miss(wrong_notes(&prompt.chord, &keys));
+                            println!("{}Try again: {}{}{}", color::Fg(color::Red), prompt, pedal_hint(input), color::Fg(color::Reset));
+                        }
+                    },
+                    None => {
+                        attempt.miss(wrong_notes(&prompt.chord, &keys));
+                        println!("{}unrecognised chord\nTry again: {}{}{}", color::Fg(color::Red), prompt, pedal_hint(input), color::Fg(color::Reset));
                     }
-                },
-                _ => {},
+                }
             }
+
+            deck.review(&prompt.key, attempt.quality(Duration::from_millis(SLOW_CHORD_MILLIS)));
+            deck.save()?;
+            round.push(attempt);
         }
 
+        round.print_summary();
+        session.record(&round)?;
+
         replay = Confirmation::new()
             .with_text("Would you like to practice again?")
             .interact()
@@ -140,12 +246,27 @@ fn practice_chords(chord_type: ChordType, inversion: usize, hand: Hand) -> Resul
     Ok(())
 }
 
-fn identify_chord() -> Option<Chord> {
-    let mut keys_down: Vec<u8> = vec!();
+pub fn chord_matches(target: &Chord, hand: Hand, played: &Chord) -> bool {
+    let octave_match = match played.octave {
+        Some(i) => hand.value().contains(&i),
+        None => true,
+    };
 
-    for i in KEYS_DOWN.lock().unwrap().as_slice() {
-        keys_down.push(*i);
-    }
+    *target == *played && octave_match
+}
+
+// number of held keys that are not part of the chord
+pub fn wrong_notes(chord: &Chord, keys: &[u8]) -> usize {
+    let root_index = note_index(&chord.root).unwrap_or(0);
+    let pitch_classes: Vec<usize> = chord.chord_type.positions().iter().map(|x| (root_index + *x as usize) % NOTE_NAMES.len()).collect();
+
+    keys.iter()
+        .filter(|x| !pitch_classes.contains(&pitch_class(**x)))
+        .count()
+}
+
+pub fn identify_chord(keys: &[u8]) -> Option<Chord> {
+    let mut keys_down: Vec<u8> = keys.to_vec();
 
     keys_down.sort_by(|a, b| a.partial_cmp(b).unwrap());
 
@@ -154,21 +275,7 @@ fn identify_chord() -> Option<Chord> {
         let root = get_note_name(keys_down[0]);
         let octave = get_octave(keys_down[0]);
 
-        let chord_type = match positions.as_slice() {
-            [0, 4, 7] => Some(ChordType::Major),
-            [0, 3, 7] => Some(ChordType::Minor),
-            [0, 3, 6] => Some(ChordType::Diminished),
-            [0, 4, 7, 11] => Some(ChordType::MajorSeventh),
-            [0, 3, 7, 10] => Some(ChordType::MinorSeventh),
-            [0, 4, 7, 10] => Some(ChordType::DominantSeventh),
-            [0, 4, 8] => Some(ChordType::Augmented),
-            [0, 2, 7] => Some(ChordType::SusTwo),
-            [0, 5, 7] => Some(ChordType::SusFour),
-            [0, 2, 7, 10] => Some(ChordType::SevenSusTwo),
-            [0, 5, 7, 10] => Some(ChordType::SevenSusFour),
-            [0, 4, 7, 9] => Some(ChordType::SusSix),
-            _ => None,
-        };
+        let chord_type = ChordType::from_positions(positions.as_slice());
 
         match chord_type {
             Some(i) => return Some(Chord{root, chord_type: i, inversion, octave}),
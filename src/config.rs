@@ -0,0 +1,144 @@
+use std::error::Error;
+use std::fs;
+use serde::{Serialize, Deserialize};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation, Input};
+
+use crate::synth::{Waveform, Envelope, WAVEFORMS};
+use crate::midi::KeyMode;
+use crate::utils::paths::config_dir;
+
+// Settings that persist between runs, kept in config.toml. Missing fields take their defaults so
+// older files keep loading as settings are added.
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Config {
+    // part of the name of the MIDI input to open without asking, or "rustykeys in" for a virtual port
+    pub midi_in: Option<String>,
+    // part of the name of a MIDI output to send prompts to
+    pub midi_out: Option<String>,
+    pub waveform: Waveform,
+    // an SF2 file to play instead of the waveform
+    pub soundfont: Option<String>,
+    // whether notes held on by the pedals count as played
+    pub key_mode: KeyMode,
+    // a table, so it has to come after the plain values for the file to be written
+    pub envelope: Envelope,
+}
+
+impl Config {
+    pub fn load() -> Result<Config, Box<dyn Error>> {
+        let path = config_dir()?.join("config.toml");
+
+        if !path.exists() {
+            return Ok(Config::default());
+        }
+
+        Ok(toml::from_str(&fs::read_to_string(&path)?)?)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let dir = config_dir()?;
+        fs::create_dir_all(&dir)?;
+        fs::write(dir.join("config.toml"), toml::to_string(self)?)?;
+
+        Ok(())
+    }
+}
+
+// a remembered port that's gone for good would otherwise be looked for every time
+fn forget_port(kind: &str, port: &mut Option<String>) {
+    if let Some(name) = port.clone() {
+        let forget = Confirmation::new()
+            .with_text(&format!("Forget the MIDI {} '{}'?", kind, name))
+            .interact()
+            .unwrap();
+
+        if forget {
+            *port = None;
+        }
+    }
+}
+
+pub fn settings_launcher() -> Result<(), Box<dyn Error>> {
+    let mut config = Config::load()?;
+
+    forget_port("input", &mut config.midi_in);
+    forget_port("output", &mut config.midi_out);
+
+    let mut sound_names: Vec<String> = WAVEFORMS.iter().map(|x| x.to_string()).collect();
+    sound_names.push("SoundFont file".to_string());
+
+    let current = match config.soundfont {
+        Some(_) => WAVEFORMS.len(),
+        None => WAVEFORMS.iter().position(|x| *x == config.waveform).unwrap_or(0),
+    };
+
+    let sound_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which sound should the synth use?")
+        .items(sound_names.as_slice())
+        .default(current)
+        .interact()
+        .unwrap();
+
+    match WAVEFORMS.get(sound_selection) {
+        Some(i) => {
+            config.waveform = *i;
+            config.soundfont = None;
+        },
+        None => {
+            let path: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt("Path to the .sf2 file")
+                .default(config.soundfont.clone().unwrap_or_default())
+                .interact()?;
+
+            config.soundfont = Some(path);
+        },
+    }
+
+    let key_mode_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which notes should count as played?")
+        .items(&["keys held down", "notes still sounding, pedals included"])
+        .default(match config.key_mode {
+            KeyMode::Held => 0,
+            KeyMode::Sounding => 1,
+        })
+        .interact()
+        .unwrap();
+
+    config.key_mode = match key_mode_selection {
+        0 => KeyMode::Held,
+        _ => KeyMode::Sounding,
+    };
+
+    let edit_envelope = Confirmation::new()
+        .with_text("Change the envelope?")
+        .interact()
+        .unwrap();
+
+    if edit_envelope {
+        let envelope = &mut config.envelope;
+
+        envelope.attack_millis = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Attack (ms)")
+            .default(envelope.attack_millis)
+            .interact()?;
+
+        envelope.decay_millis = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Decay (ms)")
+            .default(envelope.decay_millis)
+            .interact()?;
+
+        let sustain: f32 = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Sustain level (0 to 1)")
+            .default(envelope.sustain)
+            .interact()?;
+        envelope.sustain = sustain.max(0.0).min(1.0);
+
+        envelope.release_millis = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Release (ms)")
+            .default(envelope.release_millis)
+            .interact()?;
+    }
+
+    config.save()
+}
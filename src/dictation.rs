@@ -0,0 +1,225 @@
+use std::error::Error;
+use std::time::Instant;
+use rand::{thread_rng, Rng, rngs::ThreadRng, seq::SliceRandom};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
+use termion::color;
+
+use crate::midi::{midi_connect, NoteInput};
+use crate::midi_out::NoteOutput;
+use crate::synth::{Synth, SynthHandle};
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+use crate::chords::{chord_keys, identify_chord, wrong_notes, select_answer, Answer};
+use crate::progressions::{parse_numeral, numeral_root, key_name, progression_name};
+use crate::transitions::closest_inversion;
+use crate::utils::{
+    music::note_index,
+    types::{Hand, Chord},
+    constants::NOTE_NAMES,
+};
+
+const PROGRESSION_LENGTH: usize = 4;
+const ROUND_LENGTH: usize = 4;
+const CHORD_MILLIS: u64 = 1200;
+const BASS_KEY: u8 = 36;
+
+// each level adds to the numerals of the one before
+const LEVELS: &'static [(&'static str, &'static [&'static str])] = &[
+    ("I, IV and V", &["I", "IV", "V"]),
+    ("all diatonic chords", &["ii", "iii", "vi", "viio"]),
+    ("secondary dominants", &["V/V", "V/ii", "V/vi"]),
+    ("borrowed chords", &["iv", "bIII", "bVI", "bVII"]),
+];
+
+pub fn practice_dictation_launcher() -> Result<(), Box<dyn Error>> {
+    let level_names: Vec<String> = LEVELS.iter()
+        .enumerate()
+        .map(|(i, x)| match i {
+            0 => x.0.to_string(),
+            _ => format!("up to {}", x.0),
+        })
+        .collect();
+
+    let level = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which chords should come up?")
+        .items(level_names.as_slice())
+        .default(0)
+        .interact()
+        .unwrap();
+
+    let numerals: Vec<&str> = LEVELS[..=level].iter().flat_map(|x| x.1.iter().cloned()).collect();
+
+    match select_answer() {
+        Answer::Menu => practice_dictation(None, &numerals, level),
+        Answer::Keyboard => match midi_connect() {
+            Err(e) => Err(e),
+            Ok(mut conn_in) => {
+                let result = practice_dictation(Some(&mut conn_in), &numerals, level);
+                conn_in.close();
+                result
+            }
+        },
+    }
+}
+
+// Starts on I and then wanders through `numerals`, never repeating a chord straight away. A
+// secondary dominant is always followed by the chord it leads to.
+fn generate_progression(rng: &mut ThreadRng, numerals: &[&'static str]) -> Vec<&'static str> {
+    let mut progression = vec!["I"];
+
+    while progression.len() < PROGRESSION_LENGTH {
+        let numeral = *numerals.choose(rng).unwrap();
+
+        if numeral == *progression.last().unwrap() {
+            continue;
+        }
+
+        match numeral.find('/') {
+            Some(i) if progression.len() + 2 <= PROGRESSION_LENGTH => {
+                progression.push(numeral);
+                progression.push(&numeral[i + 1..]);
+            },
+            Some(_) => continue,
+            None => progression.push(numeral),
+        }
+    }
+
+    progression
+}
+
+// the chords in the key of `tonic`, each voiced close to the one before over the root in the bass
+fn progression_keys(progression: &[&str], tonic: usize) -> Vec<(Chord, Vec<u8>)> {
+    let mut chords: Vec<(Chord, Vec<u8>)> = vec!();
+
+    for numeral in progression.iter() {
+        let (root_index, root, chord_type) = numeral_root(numeral, tonic).unwrap();
+
+        let inversion = match chords.last() {
+            Some((previous, _)) => closest_inversion(previous, root, chord_type),
+            None => 0,
+        };
+
+        let chord = Chord{root: root.to_string(), chord_type, inversion, octave: None};
+
+        let mut keys = chord_keys(&chord, Hand::Right);
+        keys.insert(0, BASS_KEY + root_index as u8);
+
+        chords.push((chord, keys));
+    }
+
+    chords
+}
+
+fn play_progression(output: &mut Option<NoteOutput>, syn: Option<&SynthHandle>, chords: &[(Chord, Vec<u8>)]) {
+    for (_, keys) in chords.iter() {
+        match (output.as_mut(), syn) {
+            (Some(output), _) => output.play_chord(keys, CHORD_MILLIS),
+            (None, Some(syn)) => syn.play(keys, CHORD_MILLIS),
+            (None, None) => {},
+        }
+    }
+}
+
+// Plays a progression in a random major key, then asks for each chord after the opening I,
+// either by numeral from a menu or by playing it back in the same key.
+fn practice_dictation(mut input: Option<&mut NoteInput>, numerals: &[&'static str], level: usize) -> Result<(), Box<dyn Error>> {
+    let mut rng = thread_rng();
+    let mut output = NoteOutput::open()?;
+    let synth = match output {
+        Some(_) => None,
+        None => Some(Synth::new()?),
+    };
+    let syn = synth.as_ref().map(|x| x.handle());
+    let mut session = Session::new("dictation", &[
+        ("level", LEVELS[level].0.to_string()),
+        ("answer", if input.is_some() { "keyboard" } else { "menu" }.to_string()),
+    ]);
+
+    let mut items: Vec<&str> = numerals.to_vec();
+    items.push("hear it again");
+
+    let mut replay = true;
+
+    while replay {
+        let mut round = Round::default();
+
+        for _ in 0..ROUND_LENGTH {
+            let progression = generate_progression(&mut rng, numerals);
+            let tonic = rng.gen_range(0, NOTE_NAMES.len());
+            let chords = progression_keys(&progression, tonic);
+
+            println!("In {} major, starting on I", key_name(tonic));
+            play_progression(&mut output, syn.as_ref(), &chords);
+
+            for (i, numeral) in progression.iter().enumerate().skip(1) {
+                let chord = &chords[i].0;
+                let mut attempt = Attempt::new(format!("{} in {} ({})", numeral, progression_name(&progression), key_name(tonic)));
+
+                match input.as_mut() {
+                    None => loop {
+                        let selection = Select::with_theme(&ColorfulTheme::default())
+                            .with_prompt(&format!("Chord {} of {}?", i + 1, progression.len()))
+                            .items(&items)
+                            .interact()
+                            .unwrap();
+
+                        if selection == numerals.len() {
+                            play_progression(&mut output, syn.as_ref(), &chords);
+                        }
+                        // the same chord can be written more than one way, so compare what's played
+                        else if parse_numeral(items[selection]) == parse_numeral(numeral) {
+                            println!("{}Correct! {} is {}{}", color::Fg(color::Green), numeral, chord, color::Fg(color::Reset));
+                            attempt.finish(Instant::now());
+                            break;
+                        }
+                        else {
+                            attempt.miss(0);
+                            println!("{}Not {}{}", color::Fg(color::Red), items[selection], color::Fg(color::Reset));
+                        }
+                    },
+                    Some(input) => {
+                        println!("Play chord {} of {}", i + 1, progression.len());
+
+                        loop {
+                            let (keys, pressed) = input.next_state()?;
+
+                            match identify_chord(&keys) {
+                                Some(played) if note_index(&played.root) == note_index(&chord.root) && played.chord_type == chord.chord_type => {
+                                    println!("{}Correct! {} is {}{}", color::Fg(color::Green), numeral, chord, color::Fg(color::Reset));
+                                    attempt.finish(pressed);
+                                    break;
+                                },
+                                Some(played) => {
+                                    attempt.miss(wrong_notes(chord, &keys));
+                                    println!("{}That's {}, try again{}", color::Fg(color::Red), played, color::Fg(color::Reset));
+                                },
+                                None => {
+                                    attempt.miss(wrong_notes(chord, &keys));
+                                    println!("{}unrecognised chord, try again{}", color::Fg(color::Red), color::Fg(color::Reset));
+                                }
+                            }
+                        }
+                    },
+                }
+
+                round.push(attempt);
+            }
+
+            println!("{}", progression_name(&progression));
+        }
+
+        round.print_summary();
+        session.record(&round)?;
+
+        replay = Confirmation::new()
+            .with_text("Would you like to practice again?")
+            .interact()
+            .unwrap();
+    }
+
+    if let Some(synth) = synth {
+        synth.close()?;
+    }
+
+    Ok(())
+}
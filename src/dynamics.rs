@@ -0,0 +1,246 @@
+use std::error::Error;
+use std::io::{stdout, Write};
+use rand::{thread_rng, Rng, seq::SliceRandom};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
+use termion::color;
+
+use crate::midi::{midi_connect, NoteInput};
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+use crate::chords::identify_chord;
+use crate::utils::{
+    types::{Mode, ChordType, Chord},
+    constants::NOTE_NAMES,
+};
+
+const ROUND_LENGTH: usize = 4;
+
+// velocities that count as mezzo-forte, and how far a note may stray from the scale's average
+const MEZZO_FORTE: (u8, u8) = (56, 88);
+const EVEN_TOLERANCE: u8 = 12;
+// how much louder the top of a crescendo has to be than the bottom
+const CRESCENDO_RISE: u8 = 30;
+// how much louder a voiced top note has to be than the rest of the chord
+const VOICING_MARGIN: u8 = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Dynamics {
+    EvenScale,
+    Crescendo,
+    VoicedChord,
+}
+
+pub fn practice_dynamics_launcher() -> Result<(), Box<dyn Error>> {
+    let exercise_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Pick an exercise")
+        .items(&["even mezzo-forte scale", "crescendo across an octave", "voice the top note of a chord"])
+        .interact()
+        .unwrap();
+
+    let dynamics = match exercise_selection {
+        0 => Dynamics::EvenScale,
+        1 => Dynamics::Crescendo,
+        _ => Dynamics::VoicedChord,
+    };
+
+    match midi_connect() {
+        Err(e) => Err(e),
+        Ok(mut conn_in) => {
+            let result = practice_dynamics(&mut conn_in, dynamics);
+            conn_in.close();
+            result
+        }
+    }
+}
+
+// pitch classes of a major scale, one octave up
+fn scale_pitch_classes(root: usize) -> Vec<usize> {
+    let mut pitch_classes = vec![root];
+
+    for interval in Mode::MAJOR.value() {
+        pitch_classes.push((pitch_classes.last().unwrap() + interval) % NOTE_NAMES.len());
+    }
+
+    pitch_classes
+}
+
+// blocks until each note of the scale has been played, returning how hard each was struck
+fn play_scale(input: &mut NoteInput, root: usize, attempt: &mut Attempt) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut velocities: Vec<u8> = vec!();
+
+    for pitch_class in scale_pitch_classes(root) {
+        loop {
+            let event = input.wait_event()?;
+
+            if !event.down {
+                continue;
+            }
+
+            if event.key as usize % NOTE_NAMES.len() == pitch_class {
+                print!("{} ", event.velocity);
+                stdout().flush()?;
+                velocities.push(event.velocity);
+                attempt.finish(event.time);
+                break;
+            }
+            else {
+                attempt.miss(1);
+                print!("{}{}{} ", color::Fg(color::Red), NOTE_NAMES[event.key as usize % NOTE_NAMES.len()][0], color::Fg(color::Reset));
+                stdout().flush()?;
+            }
+        }
+    }
+
+    println!();
+    Ok(velocities)
+}
+
+// blocks until the chord is played, returning how hard each note was struck from the bottom up
+fn play_chord(input: &mut NoteInput, chord: &Chord, attempt: &mut Attempt) -> Result<Vec<u8>, Box<dyn Error>> {
+    loop {
+        let (mut keys, pressed) = input.next_state()?;
+
+        if keys.len() < chord.chord_type.positions().len() {
+            continue;
+        }
+
+        match identify_chord(&keys) {
+            Some(i) if i == *chord => {
+                attempt.finish(pressed);
+                keys.sort();
+                return Ok(keys.iter().map(|x| input.velocity(*x)).collect());
+            },
+            _ => {
+                attempt.miss(1);
+                println!("{}Try again: {}{}", color::Fg(color::Red), chord, color::Fg(color::Reset));
+            }
+        }
+    }
+}
+
+// notes played at the wrong dynamic
+fn dynamic_faults(dynamics: Dynamics, velocities: &[u8]) -> usize {
+    if velocities.is_empty() {
+        return 0;
+    }
+
+    match dynamics {
+        Dynamics::EvenScale => {
+            let mean = velocities.iter().map(|x| *x as usize).sum::<usize>() / velocities.len();
+
+            velocities.iter()
+                .filter(|x| **x < MEZZO_FORTE.0 || **x > MEZZO_FORTE.1 || (**x as i32 - mean as i32).abs() > EVEN_TOLERANCE as i32)
+                .count()
+        },
+        Dynamics::Crescendo => {
+            let dips = velocities.windows(2).filter(|x| x[1] <= x[0]).count();
+            let rise = velocities.last().unwrap().saturating_sub(velocities[0]);
+
+            match rise < CRESCENDO_RISE {
+                true => dips + 1,
+                false => dips,
+            }
+        },
+        Dynamics::VoicedChord => {
+            let (top, rest) = velocities.split_last().unwrap();
+
+            rest.iter().filter(|x| x.saturating_add(VOICING_MARGIN) > *top).count()
+        },
+    }
+}
+
+// velocity spread at each position across the round, so uneven fingers stand out
+fn print_spread(dynamics: Dynamics, velocities: &[Vec<u8>]) {
+    let positions = velocities.iter().map(|x| x.len()).max().unwrap_or(0);
+
+    if positions == 0 {
+        return;
+    }
+
+    println!("{:<10}  {:>4}  {:>4}  {:>4}  {:>6}", "Position", "Mean", "Min", "Max", "Spread");
+
+    for position in 0..positions {
+        let played: Vec<u8> = velocities.iter().filter_map(|x| x.get(position)).cloned().collect();
+        let min = *played.iter().min().unwrap();
+        let max = *played.iter().max().unwrap();
+        let mean = played.iter().map(|x| *x as usize).sum::<usize>() / played.len();
+
+        let label = match (dynamics, position) {
+            (Dynamics::VoicedChord, 0) => "bottom".to_string(),
+            (Dynamics::VoicedChord, i) if i == positions - 1 => "top".to_string(),
+            (_, i) => (i + 1).to_string(),
+        };
+
+        println!("{:<10}  {:>4}  {:>4}  {:>4}  {:>6}", label, mean, min, max, max - min);
+    }
+
+    println!();
+}
+
+fn practice_dynamics(input: &mut NoteInput, dynamics: Dynamics) -> Result<(), Box<dyn Error>> {
+    let mut session = Session::new("dynamics", &[("exercise", format!("{:?}", dynamics))]);
+    let mut rng = thread_rng();
+    let mut replay = true;
+
+    while replay {
+        let mut roots: Vec<usize> = (0..NOTE_NAMES.len()).collect();
+        roots.shuffle(&mut rng);
+
+        let mut round = Round::default();
+        let mut round_velocities: Vec<Vec<u8>> = vec!();
+
+        for root in roots.into_iter().take(ROUND_LENGTH) {
+            let root_name = NOTE_NAMES[root][0];
+
+            let (mut attempt, velocities) = match dynamics {
+                Dynamics::EvenScale | Dynamics::Crescendo => {
+                    let mut attempt = Attempt::new(format!("{} major scale", root_name));
+
+                    match dynamics {
+                        Dynamics::EvenScale => println!("Play {} major up one octave, evenly at mezzo-forte", root_name),
+                        _ => println!("Play {} major up one octave, getting louder on every note", root_name),
+                    }
+
+                    let velocities = play_scale(input, root, &mut attempt)?;
+                    (attempt, velocities)
+                },
+                Dynamics::VoicedChord => {
+                    let chord_type = *[ChordType::Major, ChordType::Minor].choose(&mut rng).unwrap();
+                    let chord = Chord{root: root_name.to_string(), chord_type, inversion: rng.gen_range(0, 3), octave: None};
+                    let mut attempt = Attempt::new(chord.to_string());
+
+                    println!("Play {}, with the top note louder than the rest", chord);
+
+                    let velocities = play_chord(input, &chord, &mut attempt)?;
+                    (attempt, velocities)
+                },
+            };
+
+            let faults = dynamic_faults(dynamics, &velocities);
+            let spread = velocities.iter().max().unwrap_or(&0) - velocities.iter().min().unwrap_or(&0);
+
+            if faults == 0 {
+                println!("{}Good! Velocity spread {}{}", color::Fg(color::Green), spread, color::Fg(color::Reset));
+            }
+            else {
+                println!("{}{} notes off the dynamic, velocity spread {}{}", color::Fg(color::Red), faults, spread, color::Fg(color::Reset));
+            }
+
+            // dynamics count against the attempt, but not as wrong notes
+            attempt.wrong_attempts += faults;
+            round.push(attempt);
+            round_velocities.push(velocities);
+        }
+
+        round.print_summary();
+        print_spread(dynamics, &round_velocities);
+        session.record(&round)?;
+
+        replay = Confirmation::new()
+            .with_text("Would you like to practice again?")
+            .interact()
+            .unwrap();
+    }
+
+    Ok(())
+}
@@ -0,0 +1,94 @@
+use std::time::{Duration, Instant};
+use termion::color;
+
+use crate::repetition::quality;
+
+pub struct Attempt {
+    pub item: String,
+    pub prompted: Instant,
+    pub duration: Option<Duration>,
+    pub wrong_attempts: usize,
+    pub wrong_notes: usize,
+}
+
+impl Attempt {
+    pub fn new(item: String) -> Attempt {
+        Attempt {
+            item,
+            prompted: Instant::now(),
+            duration: None,
+            wrong_attempts: 0,
+            wrong_notes: 0,
+        }
+    }
+
+    pub fn miss(&mut self, wrong_notes: usize) {
+        self.wrong_attempts += 1;
+        self.wrong_notes += wrong_notes;
+    }
+
+    pub fn finish(&mut self, at: Instant) {
+        self.duration = Some(at.saturating_duration_since(self.prompted));
+    }
+
+    // SM-2 quality, knocked down a grade if a clean answer took longer than `slow`
+    pub fn quality(&self, slow: Duration) -> u8 {
+        let grade = quality(self.wrong_attempts);
+
+        match self.duration {
+            Some(i) if i > slow && grade > 3 => grade - 1,
+            _ => grade,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Round {
+    pub attempts: Vec<Attempt>,
+}
+
+impl Round {
+    pub fn push(&mut self, attempt: Attempt) {
+        self.attempts.push(attempt);
+    }
+
+    pub fn print_summary(&self) {
+        let mut attempts: Vec<&Attempt> = self.attempts.iter().filter(|x| x.duration.is_some()).collect();
+
+        if attempts.is_empty() {
+            return;
+        }
+
+        // slowest first, so the items that need work are at the top
+        attempts.sort_by(|a, b| b.duration.cmp(&a.duration));
+
+        let width = attempts.iter().map(|x| x.item.len()).max().unwrap_or(0).max(4);
+
+        println!("\n{:<width$}  {:>8}  {:>14}  {:>11}", "Item", "Time", "Wrong attempts", "Wrong notes", width = width);
+
+        for attempt in attempts.iter() {
+            let line = format!("{:<width$}  {:>7.2}s  {:>14}  {:>11}",
+                attempt.item,
+                attempt.duration.unwrap().as_secs_f64(),
+                attempt.wrong_attempts,
+                attempt.wrong_notes,
+                width = width);
+
+            if attempt.wrong_attempts > 0 {
+                println!("{}{}{}", color::Fg(color::Red), line, color::Fg(color::Reset));
+            }
+            else {
+                println!("{}", line);
+            }
+        }
+
+        let total: Duration = attempts.iter().map(|x| x.duration.unwrap()).sum();
+        let wrong_attempts: usize = attempts.iter().map(|x| x.wrong_attempts).sum();
+        let wrong_notes: usize = attempts.iter().map(|x| x.wrong_notes).sum();
+
+        println!("\nMean time {:.2}s, {} wrong attempts, {} wrong notes\n",
+            total.as_secs_f64() / attempts.len() as f64,
+            wrong_attempts,
+            wrong_notes);
+    }
+}
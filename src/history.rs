@@ -0,0 +1,137 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::{self, File, OpenOptions};
+use std::io::{stdout, BufRead, BufReader, Write};
+use std::path::PathBuf;
+use chrono::Utc;
+use serde::{Serialize, Deserialize};
+
+use crate::grading::Round;
+use crate::utils::paths::data_dir;
+
+#[derive(Serialize, Deserialize)]
+pub struct ItemResult {
+    pub item: String,
+    pub millis: u64,
+    pub wrong_attempts: usize,
+    pub wrong_notes: usize,
+}
+
+// one line of the history file: a completed round of an exercise
+#[derive(Serialize, Deserialize)]
+pub struct Record {
+    pub started: String,
+    pub exercise: String,
+    pub settings: BTreeMap<String, String>,
+    pub round: usize,
+    pub results: Vec<ItemResult>,
+}
+
+pub struct Session {
+    started: String,
+    exercise: String,
+    settings: BTreeMap<String, String>,
+    rounds: usize,
+}
+
+impl Session {
+    pub fn new(exercise: &str, settings: &[(&str, String)]) -> Session {
+        Session {
+            started: Utc::now().to_rfc3339(),
+            exercise: exercise.to_string(),
+            settings: settings.iter().map(|x| (x.0.to_string(), x.1.clone())).collect(),
+            rounds: 0,
+        }
+    }
+
+    // rounds are appended as they finish, so quitting mid-session keeps what was played
+    pub fn record(&mut self, round: &Round) -> Result<(), Box<dyn Error>> {
+        self.rounds += 1;
+
+        let record = Record {
+            started: self.started.clone(),
+            exercise: self.exercise.clone(),
+            settings: self.settings.clone(),
+            round: self.rounds,
+            results: round.attempts.iter()
+                .filter_map(|x| x.duration.map(|i| ItemResult {
+                    item: x.item.clone(),
+                    millis: i.as_millis() as u64,
+                    wrong_attempts: x.wrong_attempts,
+                    wrong_notes: x.wrong_notes,
+                }))
+                .collect(),
+        };
+
+        let path = history_path()?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(&record)?)?;
+
+        Ok(())
+    }
+}
+
+fn history_path() -> Result<PathBuf, Box<dyn Error>> {
+    Ok(data_dir()?.join("history.jsonl"))
+}
+
+pub fn load() -> Result<Vec<Record>, Box<dyn Error>> {
+    let path = history_path()?;
+    let mut records = vec!();
+
+    if !path.exists() {
+        return Ok(records);
+    }
+
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+
+        if !line.trim().is_empty() {
+            records.push(serde_json::from_str(&line)?);
+        }
+    }
+
+    Ok(records)
+}
+
+pub fn export(format: &str) -> Result<(), Box<dyn Error>> {
+    let records = load()?;
+
+    match format {
+        "json" => {
+            serde_json::to_writer_pretty(stdout(), &records)?;
+            println!();
+        },
+        "csv" => {
+            // flattened to one row per item so it drops straight into a spreadsheet
+            let mut writer = csv::Writer::from_writer(stdout());
+            writer.write_record(&["started", "exercise", "settings", "round", "item", "millis", "wrong_attempts", "wrong_notes"])?;
+
+            for record in records.iter() {
+                let settings: Vec<String> = record.settings.iter().map(|(k, v)| format!("{}={}", k, v)).collect();
+
+                for result in record.results.iter() {
+                    writer.write_record(&[
+                        record.started.clone(),
+                        record.exercise.clone(),
+                        settings.join(";"),
+                        record.round.to_string(),
+                        result.item.clone(),
+                        result.millis.to_string(),
+                        result.wrong_attempts.to_string(),
+                        result.wrong_notes.to_string(),
+                    ])?;
+                }
+            }
+
+            writer.flush()?;
+        },
+        _ => return Err(format!("unknown export format '{}'", format).into()),
+    }
+
+    Ok(())
+}
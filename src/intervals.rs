@@ -1,30 +1,85 @@
-use rand::{thread_rng, Rng, seq::SliceRandom};
+use rand::{thread_rng, Rng, rngs::ThreadRng};
 use std::{thread, time};
+use std::time::Instant;
 use std::error::Error;
 use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
 use termion::color;
-use pitch_calc::{
-    Letter,
-    LetterOctave,
+use crate::utils::{
+    constants::NOTE_NAMES,
+    music::note_label,
 };
-use portaudio as pa;
-use crate::utils::constants::NOTE_NAMES;
+use crate::midi::{midi_connect, NoteInput};
 use crate::synth::Synth;
+use crate::midi_out::NoteOutput;
+use crate::repetition::Deck;
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+
+const SLOW_INTERVAL_MILLIS: u64 = 5000;
+
+pub const OCTAVE: usize = 12;
 
 const INTERVALS: &'static [&'static str] = &[
-    "0) Unison",
-    "1) Minor Second",
-    "2) Major Second",
-    "3) Minor Third",
-    "4) Major Third",
-    "5) Perfect Fourth",
-    "6) Tritone",
-    "7) Perfect Fifth",
-    "8) Minor Sixth",
-    "9) Major Sixth",
-    "10) Minor Seventh",
-    "11) Major Seventh",
-    "12) Perfect Octave"];
+    "Unison",
+    "Minor Second",
+    "Major Second",
+    "Minor Third",
+    "Major Third",
+    "Perfect Fourth",
+    "Tritone",
+    "Perfect Fifth",
+    "Minor Sixth",
+    "Major Sixth",
+    "Minor Seventh",
+    "Major Seventh",
+    "Perfect Octave",
+    "Minor Ninth",
+    "Major Ninth",
+    "Minor Tenth",
+    "Major Tenth",
+    "Perfect Eleventh",
+    "Augmented Eleventh",
+    "Perfect Twelfth",
+    "Minor Thirteenth",
+    "Major Thirteenth",
+    "Minor Fourteenth",
+    "Major Fourteenth",
+    "Double Octave"];
+
+// how intervals wider than an octave are named
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Naming {
+    // "Major Tenth"
+    Compound,
+    // "Major Third + 8ve"
+    PlusOctave,
+}
+
+pub fn interval_name(semitones: usize, naming: Naming) -> String {
+    match naming {
+        Naming::PlusOctave if semitones > OCTAVE => format!("{} + 8ve", INTERVALS[semitones - OCTAVE]),
+        _ => INTERVALS[semitones].to_string(),
+    }
+}
+
+// menu items from unison up to `largest`, numbered by semitones
+fn interval_items(largest: usize, naming: Naming) -> Vec<String> {
+    (0..=largest).map(|x| format!("{}) {}", x, interval_name(x, naming))).collect()
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Playing {
+    Melodic,
+    Harmonic,
+    Mixed,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Direction {
+    Ascending,
+    Descending,
+    Both,
+}
 
 pub fn practice_intervals_launcher() -> Result<(), Box<dyn Error>> {
     let random_root = Confirmation::new()
@@ -32,89 +87,317 @@ pub fn practice_intervals_launcher() -> Result<(), Box<dyn Error>> {
         .interact()
         .unwrap();
 
-    practice_listening(random_root)
+    let answer_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("How would you like to practice?")
+        .items(&["hear an interval and name it", "hear the root and play the interval"])
+        .default(0)
+        .interact()
+        .unwrap();
+
+    let playing = match answer_selection {
+        0 => {
+            let playing_selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("How should the intervals be played?")
+                .items(&["one note after the other", "both notes together", "a mix of both"])
+                .default(0)
+                .interact()
+                .unwrap();
+
+            match playing_selection {
+                0 => Playing::Melodic,
+                1 => Playing::Harmonic,
+                _ => Playing::Mixed,
+            }
+        },
+        _ => Playing::Melodic,
+    };
+
+    let full = match answer_selection {
+        0 => false,
+        _ => {
+            let full_selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("What should you play?")
+                .items(&["just the second note", "both notes together"])
+                .default(0)
+                .interact()
+                .unwrap();
+
+            full_selection == 1
+        }
+    };
+
+    let direction = match playing {
+        Playing::Harmonic => Direction::Ascending,
+        _ => {
+            let direction_selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("Which way should the second note go?")
+                .items(&["up", "down", "either"])
+                .default(0)
+                .interact()
+                .unwrap();
+
+            match direction_selection {
+                0 => Direction::Ascending,
+                1 => Direction::Descending,
+                _ => Direction::Both,
+            }
+        }
+    };
+
+    let compound = Confirmation::new()
+        .with_text("Would you like to include compound intervals, up to two octaves?")
+        .interact()
+        .unwrap();
+
+    let (largest, naming) = match compound {
+        false => (OCTAVE, Naming::Compound),
+        true => {
+            let naming_selection = Select::with_theme(&ColorfulTheme::default())
+                .with_prompt("How would you like to name intervals beyond the octave?")
+                .items(&["by compound name (Major Tenth)", "as an interval plus an octave (Major Third + 8ve)"])
+                .default(0)
+                .interact()
+                .unwrap();
+
+            match naming_selection {
+                0 => (INTERVALS.len() - 1, Naming::Compound),
+                _ => (INTERVALS.len() - 1, Naming::PlusOctave),
+            }
+        }
+    };
+
+    match answer_selection {
+        0 => practice_listening(random_root, playing, direction, largest, naming),
+        _ => match midi_connect() {
+            Err(e) => Err(e),
+            Ok(mut conn_in) => {
+                let result = practice_playback(&mut conn_in, random_root, full, direction, largest, naming);
+                conn_in.close();
+                result
+            }
+        },
+    }
+}
+
+fn interval_key(interval: usize) -> String {
+    format!("interval:{}", interval)
+}
+
+fn playback_key(interval: usize, full: bool) -> String {
+    format!("interval-playback:{}:{}", interval, full)
+}
+
+fn pick_root(rng: &mut ThreadRng, random_root: bool) -> usize {
+    match random_root {
+        true => rng.gen_range(0, NOTE_NAMES.len()),
+        false => 0,
+    }
+}
+
+fn pick_descending(rng: &mut ThreadRng, direction: Direction) -> bool {
+    match direction {
+        Direction::Ascending => false,
+        Direction::Descending => true,
+        Direction::Both => rng.gen(),
+    }
 }
 
-fn practice_listening(random_root: bool) -> Result<(), Box<dyn Error>> {
+// the root and the other note of the interval, with a descending interval dropping from the
+// root to the note below it
+fn interval_notes(root_index: usize, interval: usize, descending: bool) -> [u8; 2] {
+    match descending {
+        true => [60 + root_index as u8, 60 + root_index as u8 - interval as u8],
+        false => [48 + root_index as u8, 48 + root_index as u8 + interval as u8],
+    }
+}
+
+fn practice_listening(random_root: bool, playing: Playing, direction: Direction, largest: usize, naming: Naming) -> Result<(), Box<dyn Error>> {
     let mut rng = thread_rng();
+    let mut deck = Deck::load()?;
+    let mut output = NoteOutput::open()?;
+    // the synth is only needed when there's no MIDI output to play the prompts on
+    let synth = match output {
+        Some(_) => None,
+        None => Some(Synth::new()?),
+    };
+    let syn = synth.as_ref().map(|x| x.handle());
+    let mut session = Session::new("intervals", &[
+        ("random_root", random_root.to_string()),
+        ("playing", format!("{:?}", playing)),
+        ("direction", format!("{:?}", direction)),
+        ("largest", largest.to_string()),
+        ("naming", format!("{:?}", naming)),
+    ]);
+
+    let items = interval_items(largest, naming);
 
     let mut intervals: Vec<usize> = Vec::new();
-    for i in 0..INTERVALS.len() {
+    for i in 0..=largest {
         intervals.push(i);
     }
-    intervals.shuffle(&mut rng);
+    deck.sort(&mut intervals, |x| interval_key(*x));
 
-    for interval in intervals {
-        let root_index = match random_root {
-            true => rng.gen_range(0, NOTE_NAMES.len()),
-            false => 0,
-        };
-
-        let note_names = [
-            String::from(NOTE_NAMES[root_index][0]),
-            String::from(NOTE_NAMES[(root_index+interval) % NOTE_NAMES.len()][0])
-        ];
+    let mut round = Round::default();
 
-        let octaves = [
-            3,
-            3 + ((root_index + interval) / NOTE_NAMES.len())
-        ];
+    for interval in intervals {
+        let root_index = pick_root(&mut rng, random_root);
 
-        let notes = [
-            LetterOctave(get_letter(&note_names[0]), octaves[0] as i32),
-            LetterOctave(get_letter(&note_names[1]), octaves[1] as i32)
-        ];
+        let harmonic = match playing {
+            Playing::Melodic => false,
+            Playing::Harmonic => true,
+            Playing::Mixed => rng.gen(),
+        };
 
-        let pa = pa::PortAudio::new()?;
-        let syn = Synth{pa};
+        let descending = pick_descending(&mut rng, direction);
+        let keys = interval_notes(root_index, interval, descending);
 
-        for note in notes.iter() {
-            syn.play_note(note.hz() as f64, 1000, false)?;
-            thread::sleep(time::Duration::from_millis(1050));
+        match (output.as_mut(), syn.as_ref(), harmonic) {
+            (Some(output), _, true) => output.play_chord(&keys, 1000),
+            (Some(output), _, false) => output.play(&keys, 1000),
+            (None, Some(syn), true) => syn.play(&keys, 1000),
+            (None, Some(syn), false) => {
+                for key in keys.iter() {
+                    syn.play(&[*key], 1000);
+                    thread::sleep(time::Duration::from_millis(50));
+                }
+            },
+            (None, None, _) => {},
         }
 
+        let label = match (harmonic, descending) {
+            (true, _) => format!("{}, together", items[interval]),
+            (false, true) => format!("{}, down", items[interval]),
+            (false, false) => format!("{}, up", items[interval]),
+        };
+        let mut attempt = Attempt::new(label);
+
         loop {
             let interval_selection = Select::with_theme(&ColorfulTheme::default())
                 .with_prompt("What interval was this?")
-                .items(INTERVALS)
+                .items(&items)
                 .interact()
                 .unwrap();
 
             if interval_selection == interval {
                 println!("{}Correct!{}", color::Fg(color::Green), color::Fg(color::Reset));
+                attempt.finish(Instant::now());
                 break;
             }
-            else if interval_selection > interval {
+
+            attempt.miss(0);
+
+            if interval_selection > interval {
                 println!("{}Less than that!{}", color::Fg(color::Red), color::Fg(color::Reset));
             }
             else {
                 println!("{}More than that!{}", color::Fg(color::Red), color::Fg(color::Reset));
             }
         }
+
+        deck.review(&interval_key(interval), attempt.quality(time::Duration::from_millis(SLOW_INTERVAL_MILLIS)));
+        deck.save()?;
+        round.push(attempt);
+    }
+
+    round.print_summary();
+    session.record(&round)?;
+
+    if let Some(synth) = synth {
+        synth.close()?;
     }
 
     Ok(())
 }
 
-fn get_letter(note: &String) -> Letter {
-    match &note[..] {
-        "Ab" => Letter::Ab,
-        "A" => Letter::A,
-        "A#" => Letter::Ash,
-        "Bb" => Letter::Bb,
-        "B" => Letter::B,
-        "C" => Letter::C,
-        "C#" => Letter::Csh,
-        "Db" => Letter::Db,
-        "D" => Letter::D,
-        "D#" => Letter::Dsh,
-        "Eb" => Letter::Eb,
-        "E" => Letter::E,
-        "F" => Letter::F,
-        "F#" => Letter::Fsh,
-        "Gb" => Letter::Gb,
-        "G" => Letter::G,
-        "G#" => Letter::Gsh,
-        &_ => Letter::C,
+// The root is played and the student plays the interval from it on the keyboard, either just
+// the other note or both together. Only the exact keys count, so the octave matters too.
+fn practice_playback(input: &mut NoteInput, random_root: bool, full: bool, direction: Direction, largest: usize, naming: Naming) -> Result<(), Box<dyn Error>> {
+    let mut rng = thread_rng();
+    let mut deck = Deck::load()?;
+    let mut output = NoteOutput::open()?;
+    let synth = match output {
+        Some(_) => None,
+        None => Some(Synth::new()?),
+    };
+    let syn = synth.as_ref().map(|x| x.handle());
+    let mut session = Session::new("interval_playback", &[
+        ("random_root", random_root.to_string()),
+        ("full", full.to_string()),
+        ("direction", format!("{:?}", direction)),
+        ("largest", largest.to_string()),
+        ("naming", format!("{:?}", naming)),
+    ]);
+
+    let mut intervals: Vec<usize> = (0..=largest).collect();
+    deck.sort(&mut intervals, |x| playback_key(*x, full));
+
+    let mut round = Round::default();
+
+    for interval in intervals {
+        let root_index = pick_root(&mut rng, random_root);
+        let descending = pick_descending(&mut rng, direction);
+        let [root_key, target_key] = interval_notes(root_index, interval, descending);
+
+        let mut expected = match full {
+            true => vec![root_key, target_key],
+            false => vec![target_key],
+        };
+        expected.sort();
+        expected.dedup();
+
+        let label = format!("{} {} from {}", interval_name(interval, naming), if descending { "down" } else { "up" }, note_label(root_key));
+        println!("Play {}", label);
+
+        match (output.as_mut(), syn.as_ref()) {
+            (Some(output), _) => output.play(&[root_key], 1000),
+            (None, Some(syn)) => syn.play(&[root_key], 1000),
+            (None, None) => {},
+        }
+
+        let mut attempt = Attempt::new(label);
+
+        loop {
+            let (mut keys, pressed) = input.next_state()?;
+            keys.sort();
+
+            // the root may be held as a reference while the other note is found
+            if !full && target_key != root_key {
+                keys.retain(|x| *x != root_key);
+            }
+
+            if keys == expected {
+                println!("{}Correct!{}", color::Fg(color::Green), color::Fg(color::Reset));
+                attempt.finish(pressed);
+                break;
+            }
+
+            // part of the answer on its way down isn't a mistake
+            if keys.len() < expected.len() && keys.iter().all(|x| expected.contains(x)) {
+                continue;
+            }
+
+            attempt.miss(keys.iter().filter(|x| !expected.contains(x)).count());
+
+            let names: Vec<String> = keys.iter().map(|x| note_label(*x)).collect();
+            let hint = match keys.iter().find(|x| **x != root_key) {
+                Some(i) if *i > target_key => " - too high",
+                Some(i) if *i < target_key => " - too low",
+                _ => "",
+            };
+            println!("{}Not {}{}{}", color::Fg(color::Red), names.join(" "), hint, color::Fg(color::Reset));
+        }
+
+        deck.review(&playback_key(interval, full), attempt.quality(time::Duration::from_millis(SLOW_INTERVAL_MILLIS)));
+        deck.save()?;
+        round.push(attempt);
+    }
+
+    round.print_summary();
+    session.record(&round)?;
+
+    if let Some(synth) = synth {
+        synth.close()?;
     }
+
+    Ok(())
 }
@@ -0,0 +1,169 @@
+use std::collections::VecDeque;
+use std::io::{self, ErrorKind};
+use std::mem;
+use std::time::{Duration, Instant};
+
+use crate::midi::{InputEvent, KeyEvent};
+use crate::utils::{
+    music::note_label,
+    constants::KEYBOARD_DEBOUNCE_MILLIS,
+};
+
+// tracker layout: the bottom two rows play from the current octave and the top two an octave up
+const LOWER_ROW: &'static str = "zsxdcvgbhnjm,l.;/";
+const UPPER_ROW: &'static str = "q2w3er5t6y7ui9o0p";
+
+const OCTAVE_DOWN: char = '-';
+const OCTAVE_UP: char = '=';
+const RELEASE_ALL: char = ' ';
+const CTRL_C: u8 = 3;
+
+const DEFAULT_BASE: u8 = 48;
+const LOWEST_BASE: u8 = 24;
+const HIGHEST_BASE: u8 = 96;
+
+// terminals only report presses, so keys are treated as velocity 80
+const KEYBOARD_VELOCITY: u8 = 80;
+
+// Plays notes from the computer keyboard. Typed notes stay held until space is pressed, or until
+// a key is typed after a pause, so a chord is typed as a quick run of keys.
+pub struct Keyboard {
+    base: u8,
+    latched: Vec<u8>,
+    last_press: Option<Instant>,
+    pending: VecDeque<InputEvent>,
+    // the terminal settings to put back when the keyboard is dropped
+    saved: libc::termios,
+}
+
+impl Keyboard {
+    pub fn new() -> io::Result<Keyboard> {
+        let saved = enter_key_mode()?;
+
+        println!("No MIDI input found, playing from the computer keyboard.");
+        println!("{} plays from {}, {} from {}. {} and {} shift octaves, space lets go of everything\n",
+            LOWER_ROW, note_label(DEFAULT_BASE), UPPER_ROW, note_label(DEFAULT_BASE + 12), OCTAVE_DOWN, OCTAVE_UP);
+
+        Ok(Keyboard {
+            base: DEFAULT_BASE,
+            latched: vec!(),
+            last_press: None,
+            pending: VecDeque::new(),
+            saved,
+        })
+    }
+
+    fn release_all(&mut self, time: Instant) {
+        for key in self.latched.drain(..) {
+            self.pending.push_back(InputEvent::Key(KeyEvent{key, down: false, velocity: 0, time}));
+        }
+    }
+
+    fn press(&mut self, c: char) {
+        let time = Instant::now();
+
+        let offset = match (LOWER_ROW.find(c), UPPER_ROW.find(c)) {
+            (Some(i), _) => i as u8,
+            (_, Some(i)) => i as u8 + 12,
+            _ => {
+                match c {
+                    OCTAVE_DOWN => self.base = (self.base - 12).max(LOWEST_BASE),
+                    OCTAVE_UP => self.base = (self.base + 12).min(HIGHEST_BASE),
+                    RELEASE_ALL => self.release_all(time),
+                    _ => {},
+                }
+                return;
+            }
+        };
+
+        // a key typed after the last notes have settled starts a new chord
+        if let Some(i) = self.last_press {
+            if time.saturating_duration_since(i) > Duration::from_millis(KEYBOARD_DEBOUNCE_MILLIS) {
+                self.release_all(time);
+            }
+        }
+
+        let key = self.base + offset;
+        self.last_press = Some(time);
+        self.latched.retain(|x| *x != key);
+        self.latched.push(key);
+        self.pending.push_back(InputEvent::Key(KeyEvent{key, down: true, velocity: KEYBOARD_VELOCITY, time}));
+    }
+
+    // Blocks until a key is typed, returning None if `deadline` passes first. Running out of
+    // input is an error, as no more notes can ever arrive.
+    pub fn next_event(&mut self, deadline: Option<Instant>) -> io::Result<Option<InputEvent>> {
+        loop {
+            if let Some(event) = self.pending.pop_front() {
+                return Ok(Some(event));
+            }
+
+            let timeout = match deadline {
+                Some(i) => i.saturating_duration_since(Instant::now()).as_millis() as i32,
+                None => -1,
+            };
+
+            let byte = match read_byte(timeout) {
+                Ok(Some(i)) => i,
+                Ok(None) => return Ok(None),
+                // a signal such as a terminal resize interrupted the wait
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+
+            // ends the exercise by unwinding, so the terminal and any MIDI output are put back
+            if byte == CTRL_C {
+                println!();
+                return Err(io::Error::new(ErrorKind::Interrupted, "interrupted from the keyboard"));
+            }
+
+            self.press(byte as char);
+        }
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &self.saved) };
+    }
+}
+
+// Reads keys one at a time without echoing them, for as long as the keyboard is open. Unlike
+// termion's raw mode output is left alone, so exercises can keep printing lines as usual.
+fn enter_key_mode() -> io::Result<libc::termios> {
+    let mut saved: libc::termios = unsafe { mem::zeroed() };
+
+    if unsafe { libc::tcgetattr(libc::STDIN_FILENO, &mut saved) } < 0 {
+        return Err(io::Error::new(ErrorKind::Other, "playing from the computer keyboard needs a terminal"));
+    }
+
+    let mut settings = saved;
+    settings.c_lflag &= !(libc::ICANON | libc::ECHO | libc::ISIG);
+    settings.c_cc[libc::VMIN] = 1;
+    settings.c_cc[libc::VTIME] = 0;
+
+    if unsafe { libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &settings) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+
+    Ok(saved)
+}
+
+// reads straight from the file descriptor, as std's buffered stdin would hide bytes from poll
+fn read_byte(timeout_millis: i32) -> io::Result<Option<u8>> {
+    let mut fds = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+
+    match unsafe { libc::poll(&mut fds, 1, timeout_millis) } {
+        0 => return Ok(None),
+        i if i < 0 => return Err(io::Error::last_os_error()),
+        _ => {},
+    }
+
+    let mut byte: u8 = 0;
+
+    match unsafe { libc::read(libc::STDIN_FILENO, &mut byte as *mut u8 as *mut libc::c_void, 1) } {
+        1 => Ok(Some(byte)),
+        0 => Err(ErrorKind::UnexpectedEof.into()),
+        _ => Err(io::Error::last_os_error()),
+    }
+}
@@ -1,5 +1,3 @@
-#[macro_use]
-extern crate lazy_static;
 extern crate midir;
 extern crate ordinal;
 extern crate timer;
@@ -8,30 +6,178 @@ extern crate rand;
 extern crate dialoguer;
 extern crate termion;
 extern crate ndarray;
-extern crate pitch_calc;
+extern crate serde;
+extern crate serde_json;
+extern crate dirs;
+extern crate csv;
+extern crate clap;
+extern crate midly;
+extern crate libc;
+extern crate toml;
+extern crate hound;
 
 mod utils;
+mod config;
 mod midi;
+mod midi_out;
+mod keyboard;
 mod chords;
 mod scales;
 mod intervals;
 mod synth;
+mod soundfont;
+mod repetition;
+mod grading;
+mod history;
+mod transitions;
+mod progressions;
+mod sight_reading;
+mod riffs;
+mod dynamics;
+mod qualities;
+mod dictation;
+mod render;
 
 use std::error::Error;
+use std::io::{self, ErrorKind};
+use std::process;
+use std::path::Path;
 use dialoguer::{theme::ColorfulTheme, Select};
+use clap::{App, AppSettings, Arg, SubCommand};
+
+use config::{Config, settings_launcher};
+use midi::{VIRTUAL_PORT_NAME, in_port_available};
+use midi_out::out_port_available;
+use render::{render, RenderOptions, EXERCISES};
 
 use chords::practice_chords_launcher;
 use scales::practice_scales_launcher;
 use intervals::practice_intervals_launcher;
+use transitions::practice_transitions_launcher;
+use progressions::practice_progressions_launcher;
+use sight_reading::practice_sight_reading_launcher;
+use riffs::practice_riffs_launcher;
+use dynamics::practice_dynamics_launcher;
+use qualities::practice_qualities_launcher;
+use dictation::practice_dictation_launcher;
 
 fn main() -> Result<(), Box<dyn Error>> {
+    let app = App::new("rustykeys")
+        .version(env!("CARGO_PKG_VERSION"))
+        .arg(Arg::with_name("midi-in")
+            .long("midi-in")
+            .value_name("SUBSTRING")
+            .help("Open the MIDI input whose name contains SUBSTRING, and keep using it")
+            .takes_value(true))
+        .arg(Arg::with_name("midi-out")
+            .long("midi-out")
+            .value_name("SUBSTRING")
+            .help("Send prompts to the MIDI output whose name contains SUBSTRING, and keep doing so")
+            .takes_value(true))
+        .subcommand(SubCommand::with_name("history")
+            .about("Practice history")
+            .setting(AppSettings::SubcommandRequiredElseHelp)
+            .subcommand(SubCommand::with_name("export")
+                .about("Write every recorded round to stdout")
+                .arg(Arg::with_name("format")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(&["csv", "json"])
+                    .default_value("csv"))))
+        .subcommand(SubCommand::with_name("render")
+            .about("Render ear training prompts to a WAV file, with the answers in a text file beside it")
+            .arg(Arg::with_name("exercise")
+                .required(true)
+                .possible_values(EXERCISES))
+            .arg(Arg::with_name("output")
+                .long("output")
+                .short("o")
+                .value_name("FILE")
+                .takes_value(true)
+                .default_value("rustykeys.wav"))
+            .arg(Arg::with_name("count")
+                .long("count")
+                .help("Number of prompts")
+                .takes_value(true)
+                .default_value("12"))
+            .arg(Arg::with_name("gap")
+                .long("gap")
+                .value_name("SECONDS")
+                .help("Silence after each prompt to answer in")
+                .takes_value(true)
+                .default_value("4"))
+            .arg(Arg::with_name("answer-tones")
+                .long("answer-tones")
+                .help("Follow each gap with a tone and a second gap, to record the spoken answer over"))
+            .arg(Arg::with_name("fixed-root")
+                .long("fixed-root")
+                .help("Start every prompt on C rather than a random root")));
+
+    // virtual ports need ALSA or CoreMIDI
+    #[cfg(unix)]
+    let app = app.arg(Arg::with_name("virtual-in")
+        .long("virtual-in")
+        .help("Open a virtual MIDI input for other programs to play into, and keep using it")
+        .conflicts_with("midi-in"));
+
+    let matches = app.get_matches();
+
+    if let Some(history_matches) = matches.subcommand_matches("history") {
+        if let Some(export_matches) = history_matches.subcommand_matches("export") {
+            return history::export(export_matches.value_of("format").unwrap());
+        }
+    }
+
+    if let Some(render_matches) = matches.subcommand_matches("render") {
+        let gap_seconds: f64 = render_matches.value_of("gap").unwrap().parse()?;
+
+        let options = RenderOptions {
+            count: render_matches.value_of("count").unwrap().parse()?,
+            gap_millis: (gap_seconds * 1000.0) as u64,
+            answer_tones: render_matches.is_present("answer-tones"),
+            random_root: !render_matches.is_present("fixed-root"),
+        };
+
+        return render(render_matches.value_of("exercise").unwrap(), Path::new(render_matches.value_of("output").unwrap()), &options);
+    }
+
+    let midi_in = match matches.is_present("virtual-in") {
+        true => Some(VIRTUAL_PORT_NAME),
+        false => matches.value_of("midi-in"),
+    };
+
+    if midi_in.is_some() || matches.is_present("midi-out") {
+        let mut config = Config::load()?;
+        if let Some(i) = midi_in {
+            match in_port_available(i)? {
+                true => config.midi_in = Some(i.to_string()),
+                false => println!("No MIDI input matching '{}' found, so it won't be remembered", i),
+            }
+        }
+        if let Some(i) = matches.value_of("midi-out") {
+            match out_port_available(i)? {
+                true => config.midi_out = Some(i.to_string()),
+                false => println!("No MIDI output matching '{}' found, so it won't be remembered", i),
+            }
+        }
+        config.save()?;
+    }
+
     let options = &[
         "Practice chords",
         "Practice scales",
         "Practice intervals",
+        "Practice chord transitions",
+        "Practice chord progressions",
+        "Practice sight reading",
+        "Practice a riff from a MIDI file",
+        "Practice dynamics",
+        "Identify chords by ear",
+        "Transcribe chord progressions by ear",
+        "Settings",
     ];
 
-    match Select::with_theme(&ColorfulTheme::default())
+    let result = match Select::with_theme(&ColorfulTheme::default())
         .with_prompt("What would you like to do?")
         .items(options)
         .interact()
@@ -40,6 +186,21 @@ fn main() -> Result<(), Box<dyn Error>> {
         0 => practice_chords_launcher(),
         1 => practice_scales_launcher(),
         2 => practice_intervals_launcher(),
+        3 => practice_transitions_launcher(),
+        4 => practice_progressions_launcher(),
+        5 => practice_sight_reading_launcher(),
+        6 => practice_riffs_launcher(),
+        7 => practice_dynamics_launcher(),
+        8 => practice_qualities_launcher(),
+        9 => practice_dictation_launcher(),
+        10 => settings_launcher(),
         _ => Ok(()),
+    };
+
+    // Ctrl-C on the computer keyboard unwinds out of the exercise, so everything has been let go
+    // of by the time it gets here
+    match result {
+        Err(ref e) if e.downcast_ref::<io::Error>().map_or(false, |x| x.kind() == ErrorKind::Interrupted) => process::exit(130),
+        result => result,
     }
 }
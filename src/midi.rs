@@ -1,67 +1,798 @@
 use midir::{MidiInputConnection, MidiInput, Ignore};
-use std::io::{stdin, stdout, Write};
+#[cfg(unix)]
+use midir::os::unix::VirtualInput;
+use dialoguer::{theme::ColorfulTheme, Select};
 use std::error::Error;
-use std::time::Instant;
+use std::fmt;
+use std::io::{self, ErrorKind};
+use std::collections::VecDeque;
+use std::sync::mpsc::{channel, Sender, Receiver, RecvTimeoutError};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
 
-use crate::utils::mutex::{LAST_KEY_PRESS, KEYS_DOWN};
+use crate::config::Config;
+use crate::keyboard::Keyboard;
+use crate::utils::constants::{DEBOUNCE_MILLIS, KEYBOARD_DEBOUNCE_MILLIS};
 
-pub fn midi_connect() -> Result<MidiInputConnection<()>, Box<dyn Error>> {
+#[derive(Debug, Copy, Clone)]
+pub struct KeyEvent {
+    pub key: u8,
+    pub down: bool,
+    // 1-127 for key presses, 0 for releases
+    pub velocity: u8,
+    pub time: Instant,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub enum InputEvent {
+    Key(KeyEvent),
+    Pedal { controller: u8, down: bool },
+    // the device went away, so anything held is gone
+    Disconnected,
+}
+
+// Which notes an exercise sees: the keys under the player's fingers, or everything still
+// sounding because the sustain or sostenuto pedal is holding the dampers off.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeyMode {
+    Held,
+    Sounding,
+}
+
+impl Default for KeyMode {
+    fn default() -> KeyMode {
+        KeyMode::Held
+    }
+}
+
+#[derive(Debug, Default, Copy, Clone)]
+pub struct Pedals {
+    pub sustain: bool,
+    pub sostenuto: bool,
+    pub soft: bool,
+}
+
+impl fmt::Display for Pedals {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names: Vec<&str> = [(self.sustain, "sustain"), (self.sostenuto, "sostenuto"), (self.soft, "soft")]
+            .iter()
+            .filter(|x| x.0)
+            .map(|x| x.1)
+            .collect();
+
+        write!(f, "{}", names.join(", "))
+    }
+}
+
+const SUSTAIN_PEDAL: u8 = 64;
+const SOSTENUTO_PEDAL: u8 = 66;
+const SOFT_PEDAL: u8 = 67;
+
+// the port a DAW, sequencer or aconnect can route into, instead of opening a device
+pub const VIRTUAL_PORT_NAME: &'static str = "rustykeys in";
+
+// how often to check that the device is still plugged in while waiting for input
+const PORT_CHECK_MILLIS: u64 = 1000;
+
+struct MidiSource {
+    conn: Option<MidiInputConnection<()>>,
+    port_name: String,
+    // a second client, only used to list ports
+    scanner: MidiInput,
+    events: Receiver<InputEvent>,
+    // keeping a sender here means the channel can never hang up while the input is open
+    sender: Sender<InputEvent>,
+}
+
+impl MidiSource {
+    fn port_present(&self) -> bool {
+        self.port_name == VIRTUAL_PORT_NAME || find_port(&self.scanner, &self.port_name).is_some()
+    }
+
+    // Blocks until a device with the same name is plugged back in and connected. The exercise
+    // is simply paused in the meantime, so nothing done so far is lost.
+    fn reconnect(&mut self) {
+        if let Some(conn) = self.conn.take() {
+            conn.close();
+        }
+
+        let _ = self.sender.send(InputEvent::Disconnected);
+
+        println!("\nMIDI input '{}' disconnected, waiting for it to come back...", self.port_name);
+
+        loop {
+            thread::sleep(Duration::from_millis(PORT_CHECK_MILLIS));
+
+            let conn = MidiInput::new("midir forwarding input")
+                .ok()
+                .and_then(|x| find_port(&x, &self.port_name).map(|i| (x, i)))
+                .and_then(|(x, i)| x.connect(i, "midir-read-input", forward(self.sender.clone()), ()).ok());
+
+            if let Some(conn) = conn {
+                self.conn = Some(conn);
+                println!("Reconnected to '{}'\n", self.port_name);
+                return;
+            }
+        }
+    }
+
+    fn recv(&mut self, deadline: Option<Instant>) -> Option<InputEvent> {
+        loop {
+            let now = Instant::now();
+            let check = now + Duration::from_millis(PORT_CHECK_MILLIS);
+            let wait_until = match deadline {
+                Some(i) if i < check => i,
+                _ => check,
+            };
+
+            match self.events.recv_timeout(wait_until.saturating_duration_since(now)) {
+                Ok(event) => return Some(event),
+                Err(RecvTimeoutError::Timeout) => {
+                    if !self.port_present() {
+                        self.reconnect();
+                    }
+                    else if deadline.map_or(false, |x| Instant::now() >= x) {
+                        return None;
+                    }
+                },
+                Err(RecvTimeoutError::Disconnected) => return None,
+            }
+        }
+    }
+}
+
+enum Source {
+    Midi(MidiSource),
+    Keyboard(Keyboard),
+    // nothing to read from, so tests can hand events straight to `receive`
+    #[cfg(test)]
+    Detached,
+}
+
+// Key events arrive over a channel from the MIDI callback, or from the computer keyboard, and the
+// held keys are tracked on the receiving side, so exercises sleep until there is input rather
+// than polling shared state.
+pub struct NoteInput {
+    source: Source,
+    debounce: Duration,
+    // the notes exercises see, as chosen by `key_mode`
+    keys_down: Vec<u8>,
+    key_mode: KeyMode,
+    held: Vec<u8>,
+    // released while the sustain pedal was down
+    sustained: Vec<u8>,
+    // held when the sostenuto pedal went down
+    sostenuto_keys: Vec<u8>,
+    pedals: Pedals,
+    // how hard each key was last struck, indexed by note number
+    velocities: Vec<u8>,
+    // note offs produced by lifting a pedal, handed out one at a time
+    pending: VecDeque<KeyEvent>,
+}
+
+impl NoteInput {
+    fn new(source: Source, debounce_millis: u64) -> NoteInput {
+        NoteInput {
+            source,
+            debounce: Duration::from_millis(debounce_millis),
+            keys_down: vec!(),
+            key_mode: KeyMode::Held,
+            held: vec!(),
+            sustained: vec!(),
+            sostenuto_keys: vec!(),
+            pedals: Pedals::default(),
+            velocities: vec![0; 128],
+            pending: VecDeque::new(),
+        }
+    }
+
+    pub fn close(self) {
+        if let Source::Midi(MidiSource { conn: Some(conn), .. }) = self.source {
+            conn.close();
+        }
+    }
+
+    pub fn set_key_mode(&mut self, key_mode: KeyMode) {
+        self.key_mode = key_mode;
+        self.keys_down = match key_mode {
+            KeyMode::Held => self.held.clone(),
+            KeyMode::Sounding => self.sounding(),
+        };
+    }
+
+    pub fn pedals(&self) -> Pedals {
+        self.pedals
+    }
+
+    pub fn velocity(&self, key: u8) -> u8 {
+        self.velocities[key as usize]
+    }
+
+    fn sounding(&self) -> Vec<u8> {
+        let mut keys = self.held.clone();
+
+        for key in self.sustained.iter().chain(self.sostenuto_keys.iter()) {
+            if !keys.contains(key) {
+                keys.insert(0, *key);
+            }
+        }
+
+        keys
+    }
+
+    fn apply(&mut self, event: &KeyEvent) {
+        self.keys_down.retain(|x| *x != event.key);
+
+        // the most recently pressed key is always last
+        if event.down {
+            self.keys_down.push(event.key);
+        }
+    }
+
+    fn still_sounding(&self, key: u8) -> bool {
+        self.key_mode == KeyMode::Sounding
+            && (self.held.contains(&key)
+                || (self.pedals.sustain && self.sustained.contains(&key))
+                || (self.pedals.sostenuto && self.sostenuto_keys.contains(&key)))
+    }
+
+    // updates held keys and pedals, returning the key event the exercise should see, if any
+    fn receive(&mut self, event: InputEvent) -> Option<KeyEvent> {
+        match event {
+            InputEvent::Key(key_event) => {
+                self.held.retain(|x| *x != key_event.key);
+
+                if key_event.down {
+                    self.held.push(key_event.key);
+                    self.velocities[key_event.key as usize] = key_event.velocity;
+                    self.sustained.retain(|x| *x != key_event.key);
+                }
+                else if self.pedals.sustain {
+                    self.sustained.push(key_event.key);
+                }
+
+                match key_event.down || !self.still_sounding(key_event.key) {
+                    true => Some(key_event),
+                    false => None,
+                }
+            },
+            InputEvent::Disconnected => {
+                let now = Instant::now();
+
+                for key in self.keys_down.iter() {
+                    self.pending.push_back(KeyEvent{key: *key, down: false, velocity: 0, time: now});
+                }
+
+                self.held.clear();
+                self.sustained.clear();
+                self.sostenuto_keys.clear();
+                self.pedals = Pedals::default();
+                self.pending.pop_front()
+            },
+            InputEvent::Pedal { controller, down } => {
+                let released: Vec<u8> = match (controller, down) {
+                    (SUSTAIN_PEDAL, _) => {
+                        self.pedals.sustain = down;
+                        match down {
+                            true => vec!(),
+                            false => self.sustained.drain(..).collect(),
+                        }
+                    },
+                    (SOSTENUTO_PEDAL, true) => {
+                        self.pedals.sostenuto = true;
+                        self.sostenuto_keys = self.held.clone();
+                        vec!()
+                    },
+                    (SOSTENUTO_PEDAL, false) => {
+                        self.pedals.sostenuto = false;
+                        self.sostenuto_keys.drain(..).collect()
+                    },
+                    (SOFT_PEDAL, _) => {
+                        self.pedals.soft = down;
+                        vec!()
+                    },
+                    _ => vec!(),
+                };
+
+                if self.key_mode == KeyMode::Sounding {
+                    let now = Instant::now();
+
+                    for key in released {
+                        if !self.still_sounding(key) && !self.pending.iter().any(|x| x.key == key) {
+                            self.pending.push_back(KeyEvent{key, down: false, velocity: 0, time: now});
+                        }
+                    }
+                }
+
+                self.pending.pop_front()
+            },
+        }
+    }
+
+    // blocks until the next raw event from the device, or until `deadline` if there is one
+    fn receive_raw(&mut self, deadline: Option<Instant>) -> io::Result<Option<InputEvent>> {
+        match &mut self.source {
+            Source::Midi(midi) => Ok(midi.recv(deadline)),
+            Source::Keyboard(keyboard) => keyboard.next_event(deadline),
+            #[cfg(test)]
+            Source::Detached => Ok(None),
+        }
+    }
+
+    fn next_key_event(&mut self, deadline: Option<Instant>) -> io::Result<Option<KeyEvent>> {
+        loop {
+            let event = match self.pending.pop_front() {
+                Some(i) => i,
+                None => {
+                    let received = match self.receive_raw(deadline)? {
+                        Some(i) => i,
+                        None => return Ok(None),
+                    };
+                    match self.receive(received) {
+                        Some(i) => i,
+                        None => continue,
+                    }
+                }
+            };
+
+            self.apply(&event);
+            return Ok(Some(event));
+        }
+    }
+
+    // blocks until the next key event
+    pub fn wait_event(&mut self) -> io::Result<KeyEvent> {
+        match self.next_key_event(None)? {
+            Some(event) => Ok(event),
+            None => Err(io::Error::new(ErrorKind::UnexpectedEof, "note input closed")),
+        }
+    }
+
+    // blocks until the next key event, or gives up after `timeout`
+    pub fn next_event(&mut self, timeout: Duration) -> io::Result<Option<KeyEvent>> {
+        self.next_key_event(Some(Instant::now() + timeout))
+    }
+
+    // Blocks until the keys have settled for the debounce time with something held, returning
+    // the held keys and when they last changed.
+    pub fn next_state(&mut self) -> io::Result<(Vec<u8>, Instant)> {
+        loop {
+            let mut last_change = self.wait_event()?.time;
+
+            while let Some(event) = self.next_event(self.debounce)? {
+                last_change = event.time;
+            }
+
+            if !self.keys_down.is_empty() {
+                return Ok((self.keys_down.clone(), last_change));
+            }
+        }
+    }
+}
+
+enum InPort {
+    Device(usize),
+    // virtual ports are only available through ALSA and CoreMIDI
+    #[cfg(unix)]
+    Virtual,
+}
+
+pub fn midi_connect() -> Result<NoteInput, Box<dyn Error>> {
+    let key_mode = Config::load()?.key_mode;
     let mut midi_in = MidiInput::new("midir forwarding input")?;
     midi_in.ignore(Ignore::None);
 
-    let in_port = match get_in_port(&midi_in) {
-        Ok(i)  => i,
-        Err(e) => return Err(e),
+    let in_port = match get_in_port(&midi_in)? {
+        Some(i) => i,
+        None => {
+            let mut input = NoteInput::new(Source::Keyboard(Keyboard::new()?), KEYBOARD_DEBOUNCE_MILLIS);
+            input.set_key_mode(key_mode);
+            return Ok(input);
+        },
     };
 
-    let in_port_name = midi_in.port_name(in_port)?;
-
     println!("\nOpening connection...");
 
-    let conn_in = midi_in.connect(in_port, "midir-read-input", move |_, message, _| {
-        *LAST_KEY_PRESS.lock().unwrap() = Some(Instant::now());
-        process_msg(message);
-    }, ())?;
+    let (sender, events) = channel();
+
+    let (conn_in, in_port_name) = match in_port {
+        InPort::Device(i) => {
+            let in_port_name = midi_in.port_name(i)?;
+            (midi_in.connect(i, "midir-read-input", forward(sender.clone()), ())?, in_port_name)
+        },
+        #[cfg(unix)]
+        InPort::Virtual => {
+            let conn_in = midi_in.create_virtual(VIRTUAL_PORT_NAME, forward(sender.clone()), ())?;
+            println!("Created virtual port '{}', route a device or program into it", VIRTUAL_PORT_NAME);
+            (conn_in, VIRTUAL_PORT_NAME.to_string())
+        },
+    };
 
     println!("Connection open, reading input from '{}'. Press ^C to quit\n", in_port_name);
 
-    Ok(conn_in)
+    let mut input = NoteInput::new(Source::Midi(MidiSource {
+        conn: Some(conn_in),
+        port_name: in_port_name,
+        scanner: MidiInput::new("rustykeys port scanner")?,
+        events,
+        sender,
+    }), DEBOUNCE_MILLIS);
+    input.set_key_mode(key_mode);
+
+    Ok(input)
+}
+
+// the MIDI callback, parsing messages and passing them on to the NoteInput
+fn forward(sender: Sender<InputEvent>) -> impl FnMut(u64, &[u8], &mut ()) + Send + 'static {
+    let mut parser = MidiParser::default();
+
+    move |_, message, _| {
+        for msg in parser.parse(message) {
+            if let Some(event) = process_msg(msg) {
+                let _ = sender.send(event);
+            }
+        }
+    }
+}
+
+// ALSA appends client and port numbers to names, and those change when a device is replugged
+fn device_name(port_name: &str) -> &str {
+    match port_name.rfind(' ') {
+        Some(i) if port_name[i + 1..].contains(':') && port_name[i + 1..].chars().all(|x| x.is_ascii_digit() || x == ':') => &port_name[..i],
+        _ => port_name,
+    }
 }
 
-fn get_in_port(midi_in: &MidiInput) -> Result<usize, Box<dyn Error>> {
-    let in_port = match midi_in.port_count() {
-        0 => return Err("no input port found".into()),
+fn find_port(midi_in: &MidiInput, port_name: &str) -> Option<usize> {
+    (0..midi_in.port_count()).find(|x| match midi_in.port_name(*x) {
+        Ok(name) => device_name(&name) == device_name(port_name),
+        Err(_) => false,
+    })
+}
+
+// the first port whose name contains `preferred`, ignoring case and ALSA's numbers on either
+fn matching_port(port_names: &[String], preferred: &str) -> Option<usize> {
+    let preferred = device_name(preferred).to_lowercase();
+
+    port_names.iter().position(|x| device_name(x).to_lowercase().contains(&preferred))
+}
+
+// whether --midi-in names a port that can be opened right now, so a typo isn't saved
+pub fn in_port_available(preferred: &str) -> Result<bool, Box<dyn Error>> {
+    #[cfg(unix)]
+    {
+        if preferred == VIRTUAL_PORT_NAME {
+            return Ok(true);
+        }
+    }
+
+    let midi_in = MidiInput::new("rustykeys port scanner")?;
+    let port_names: Vec<String> = (0..midi_in.port_count())
+        .map(|x| midi_in.port_name(x).unwrap_or_default())
+        .collect();
+
+    Ok(matching_port(&port_names, preferred).is_some())
+}
+
+// Opens the saved or --midi-in device if one matches, otherwise asks, and remembers the choice.
+// None means there is nothing to open, so the computer keyboard should be used.
+fn get_in_port(midi_in: &MidiInput) -> Result<Option<InPort>, Box<dyn Error>> {
+    let mut config = Config::load()?;
+    let port_names: Vec<String> = (0..midi_in.port_count())
+        .map(|x| midi_in.port_name(x).unwrap_or_default())
+        .collect();
+
+    if let Some(preferred) = &config.midi_in {
+        #[cfg(unix)]
+        {
+            if preferred == VIRTUAL_PORT_NAME {
+                return Ok(Some(InPort::Virtual));
+            }
+        }
+
+        if let Some(i) = matching_port(&port_names, preferred) {
+            return Ok(Some(InPort::Device(i)));
+        }
+
+        println!("No MIDI input matching '{}' found", preferred);
+    }
+
+    let mut items = port_names.clone();
+    #[cfg(unix)]
+    items.push(format!("virtual port '{}'", VIRTUAL_PORT_NAME));
+
+    // with no devices plugged in, the virtual port is offered alongside the computer keyboard
+    let keyboard_item = items.len();
+    if port_names.is_empty() {
+        items.push("computer keyboard".to_string());
+    }
+
+    let selection = match items.len() {
+        1 if port_names.is_empty() => return Ok(None),
         1 => {
-            println!("Choosing the only available input port: {}", midi_in.port_name(0).unwrap());
+            println!("Choosing the only available input port: {}", port_names[0]);
             0
         },
-        _ => {
-            println!("\nAvailable input ports:");
-            for i in 0..midi_in.port_count() {
-                println!("{}: {}", i, midi_in.port_name(i).unwrap())
+        _ => Select::with_theme(&ColorfulTheme::default())
+            .with_prompt("Pick a MIDI input")
+            .items(items.as_slice())
+            .default(0)
+            .interact()?,
+    };
+
+    let in_port = match selection {
+        i if i < port_names.len() => InPort::Device(i),
+        i if i == keyboard_item => return Ok(None),
+        #[cfg(unix)]
+        _ => InPort::Virtual,
+        #[cfg(not(unix))]
+        _ => return Ok(None),
+    };
+
+    config.midi_in = Some(match in_port {
+        InPort::Device(i) => device_name(&port_names[i]).to_string(),
+        #[cfg(unix)]
+        InPort::Virtual => VIRTUAL_PORT_NAME.to_string(),
+    });
+    config.save()?;
+
+    Ok(Some(in_port))
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MidiMessage {
+    NoteOn { key: u8, velocity: u8 },
+    NoteOff { key: u8 },
+    ControlChange { controller: u8, value: u8 },
+}
+
+// MIDI 1.0 byte stream parser. Channel messages on any channel are understood, running status
+// is followed, and anything else (system messages, stray data bytes, truncated messages) is
+// skipped rather than trusted.
+#[derive(Default)]
+pub struct MidiParser {
+    running_status: Option<u8>,
+}
+
+impl MidiParser {
+    pub fn parse(&mut self, bytes: &[u8]) -> Vec<MidiMessage> {
+        let mut messages = vec!();
+        let mut i = 0;
+
+        while i < bytes.len() {
+            let byte = bytes[i];
+
+            // real-time messages are a single byte and can appear anywhere, even mid-message
+            if byte >= 0xF8 {
+                i += 1;
+                continue;
+            }
+
+            let status = if byte >= 0x80 {
+                i += 1;
+                byte
+            }
+            else {
+                match self.running_status {
+                    Some(status) => status,
+                    None => {
+                        i += 1;
+                        continue;
+                    }
+                }
+            };
+
+            let data_len = match status {
+                0x80..=0xBF | 0xE0..=0xEF => 2,
+                0xC0..=0xDF => 1,
+                0xF1 | 0xF3 => 1,
+                0xF2 => 2,
+                _ => 0,
+            };
+
+            // system common and exclusive messages cancel running status
+            self.running_status = match status {
+                0x80..=0xEF => Some(status),
+                _ => None,
+            };
+
+            if status == 0xF0 {
+                while i < bytes.len() && bytes[i] != 0xF7 && (bytes[i] < 0x80 || bytes[i] >= 0xF8) {
+                    i += 1;
+                }
+                continue;
+            }
+
+            let mut data: Vec<u8> = vec!();
+
+            while data.len() < data_len && i < bytes.len() {
+                match bytes[i] {
+                    // real-time bytes are dropped in place, leaving the message to carry on after them
+                    x if x >= 0xF8 => {},
+                    x if x < 0x80 => data.push(x),
+                    _ => break,
+                }
+                i += 1;
+            }
+
+            if data.len() < data_len {
+                continue;
+            }
+
+            match status & 0xF0 {
+                0x90 if data[1] > 0 => messages.push(MidiMessage::NoteOn{key: data[0], velocity: data[1]}),
+                // note on with zero velocity is how many keyboards send note off
+                0x80 | 0x90 => messages.push(MidiMessage::NoteOff{key: data[0]}),
+                0xB0 => messages.push(MidiMessage::ControlChange{controller: data[0], value: data[1]}),
+                _ => {},
             }
-            print!("Please select input port: ");
-            stdout().flush()?;
-            let mut input = String::new();
-            stdin().read_line(&mut input)?;
-            input.trim().parse::<usize>()?
         }
-    };
 
-    Ok(in_port)
+        messages
+    }
 }
 
-fn process_msg(msg: &[u8]) {
-    match msg[0] {
-        0x90 => {
-            *LAST_KEY_PRESS.lock().unwrap() = Some(Instant::now());
-            KEYS_DOWN.lock().unwrap().push(msg[1]);
-        },
-        0x80 => {
-            let index = KEYS_DOWN.lock().unwrap().iter().position(|x| *x == msg[1]).unwrap();
-            KEYS_DOWN.lock().unwrap().remove(index);
+fn process_msg(msg: MidiMessage) -> Option<InputEvent> {
+    match msg {
+        MidiMessage::NoteOn { key, velocity } => Some(InputEvent::Key(KeyEvent{key, down: true, velocity, time: Instant::now()})),
+        MidiMessage::NoteOff { key } => Some(InputEvent::Key(KeyEvent{key, down: false, velocity: 0, time: Instant::now()})),
+        // pedals are switches on most keyboards; half-pedalling counts as down from the midpoint
+        MidiMessage::ControlChange { controller, value } => match controller {
+            SUSTAIN_PEDAL | SOSTENUTO_PEDAL | SOFT_PEDAL => Some(InputEvent::Pedal{controller, down: value >= 64}),
+            _ => None,
         },
-        _ => {},
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn note_on(key: u8, velocity: u8) -> MidiMessage {
+        MidiMessage::NoteOn{key, velocity}
+    }
+
+    #[test]
+    fn follows_running_status() {
+        let mut parser = MidiParser::default();
+
+        assert_eq!(parser.parse(&[0x90, 60, 100, 64, 90, 60, 0]), vec![note_on(60, 100), note_on(64, 90), MidiMessage::NoteOff{key: 60}]);
+        // and across calls, as a device can send each note separately
+        assert_eq!(parser.parse(&[67, 80]), vec![note_on(67, 80)]);
+    }
+
+    #[test]
+    fn skips_sysex() {
+        let mut parser = MidiParser::default();
+
+        assert_eq!(parser.parse(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7, 0x90, 60, 100]), vec![note_on(60, 100)]);
+        // running status doesn't carry over a system exclusive message
+        assert_eq!(parser.parse(&[0xF0, 0x01, 0xF7, 62, 100]), vec![]);
+    }
+
+    fn detached(key_mode: KeyMode) -> NoteInput {
+        let mut input = NoteInput::new(Source::Detached, 0);
+        input.set_key_mode(key_mode);
+        input
+    }
+
+    fn key(key: u8, down: bool) -> InputEvent {
+        InputEvent::Key(KeyEvent{key, down, velocity: if down { 80 } else { 0 }, time: Instant::now()})
+    }
+
+    fn pedal(controller: u8, down: bool) -> InputEvent {
+        InputEvent::Pedal{controller, down}
+    }
+
+    // the key events an exercise would see after `event`, as (key, down)
+    fn feed(input: &mut NoteInput, event: InputEvent) -> Vec<(u8, bool)> {
+        let mut seen: Vec<KeyEvent> = input.receive(event).into_iter().collect();
+
+        for event in seen.iter() {
+            input.apply(event);
+        }
+
+        while let Some(event) = input.next_key_event(Some(Instant::now())).unwrap() {
+            seen.push(event);
+        }
+
+        seen.iter().map(|x| (x.key, x.down)).collect()
+    }
+
+    #[test]
+    fn held_keys_ignore_the_pedals() {
+        let mut input = detached(KeyMode::Held);
+
+        assert_eq!(feed(&mut input, pedal(SUSTAIN_PEDAL, true)), vec![]);
+        assert_eq!(feed(&mut input, key(60, true)), vec![(60, true)]);
+        assert_eq!(feed(&mut input, key(60, false)), vec![(60, false)]);
+        assert!(input.keys_down.is_empty());
+        assert!(input.pedals().sustain);
+        assert_eq!(input.velocity(60), 80);
+    }
+
+    #[test]
+    fn sustained_notes_sound_until_the_pedal_lifts() {
+        let mut input = detached(KeyMode::Sounding);
+
+        feed(&mut input, pedal(SUSTAIN_PEDAL, true));
+        feed(&mut input, key(60, true));
+        feed(&mut input, key(64, true));
+        assert_eq!(feed(&mut input, key(60, false)), vec![]);
+        assert_eq!(input.keys_down, vec![60, 64]);
+
+        // 64 is still held down, so only 60 stops
+        assert_eq!(feed(&mut input, pedal(SUSTAIN_PEDAL, false)), vec![(60, false)]);
+        assert_eq!(input.keys_down, vec![64]);
+    }
+
+    #[test]
+    fn sostenuto_only_holds_keys_down_when_pressed() {
+        let mut input = detached(KeyMode::Sounding);
+
+        feed(&mut input, key(60, true));
+        feed(&mut input, pedal(SOSTENUTO_PEDAL, true));
+        feed(&mut input, key(64, true));
+        assert_eq!(feed(&mut input, key(60, false)), vec![]);
+        assert_eq!(feed(&mut input, key(64, false)), vec![(64, false)]);
+        assert_eq!(input.keys_down, vec![60]);
+
+        assert_eq!(feed(&mut input, pedal(SOSTENUTO_PEDAL, false)), vec![(60, false)]);
+        assert!(input.keys_down.is_empty());
+    }
+
+    #[test]
+    fn switching_modes_shows_what_is_sounding() {
+        let mut input = detached(KeyMode::Held);
+
+        feed(&mut input, pedal(SUSTAIN_PEDAL, true));
+        feed(&mut input, key(60, true));
+        feed(&mut input, key(60, false));
+        assert!(input.keys_down.is_empty());
+
+        input.set_key_mode(KeyMode::Sounding);
+        assert_eq!(input.keys_down, vec![60]);
+    }
+
+    #[test]
+    fn disconnecting_lets_go_of_everything() {
+        let mut input = detached(KeyMode::Sounding);
+
+        feed(&mut input, pedal(SUSTAIN_PEDAL, true));
+        feed(&mut input, key(60, true));
+        feed(&mut input, key(64, true));
+        feed(&mut input, key(60, false));
+
+        assert_eq!(feed(&mut input, InputEvent::Disconnected), vec![(60, false), (64, false)]);
+        assert!(input.keys_down.is_empty());
+        assert!(!input.pedals().sustain);
+    }
+
+    #[test]
+    fn device_names_drop_alsa_numbers() {
+        assert_eq!(device_name("USB Keyboard 24:0"), "USB Keyboard");
+        assert_eq!(device_name("Digital Piano MIDI 1"), "Digital Piano MIDI 1");
+        assert_eq!(device_name("Digital Piano"), "Digital Piano");
+    }
+
+    #[test]
+    fn ports_match_after_replugging() {
+        let port_names = vec!["Midi Through 14:0".to_string(), "USB Keyboard 28:0".to_string()];
+
+        assert_eq!(matching_port(&port_names, "USB Keyboard 24:0"), Some(1));
+        assert_eq!(matching_port(&port_names, "USB Keyboard"), Some(1));
+        assert_eq!(matching_port(&port_names, "usb"), Some(1));
+        assert_eq!(matching_port(&port_names, "Digital Piano"), None);
+    }
+
+    #[test]
+    fn real_time_bytes_dont_interrupt_messages() {
+        let mut parser = MidiParser::default();
+
+        assert_eq!(parser.parse(&[0x90, 0xF8, 60, 0xFE, 100, 0xF8]), vec![note_on(60, 100)]);
+        assert_eq!(parser.parse(&[0xF8, 64, 0xFA, 90]), vec![note_on(64, 90)]);
+        assert_eq!(parser.parse(&[0xF0, 0x01, 0xF8, 0x02, 0xF7, 0xB0, 64, 127]), vec![MidiMessage::ControlChange{controller: 64, value: 127}]);
+    }
+}
@@ -0,0 +1,103 @@
+use midir::{MidiOutput, MidiOutputConnection};
+use std::error::Error;
+use std::thread;
+use std::time::Duration;
+
+use crate::config::Config;
+
+const NOTE_ON: u8 = 0x90;
+const NOTE_OFF: u8 = 0x80;
+const VELOCITY: u8 = 80;
+
+// the first output whose name contains `preferred`, ignoring case
+fn find_port(midi_out: &MidiOutput, preferred: &str) -> Option<usize> {
+    let preferred = preferred.to_lowercase();
+
+    (0..midi_out.port_count()).find(|x| match midi_out.port_name(*x) {
+        Ok(name) => name.to_lowercase().contains(&preferred),
+        Err(_) => false,
+    })
+}
+
+// whether --midi-out names an output that's plugged in, so a typo isn't saved
+pub fn out_port_available(preferred: &str) -> Result<bool, Box<dyn Error>> {
+    Ok(find_port(&MidiOutput::new("rustykeys port scanner")?, preferred).is_some())
+}
+
+// Sends prompts to the player's own keyboard or soft-synth, for a real piano sound and, on
+// keyboards with light-up keys, to show which keys to press. Notes still held are let go when
+// the output is dropped, so nothing is left hanging if an exercise bails out early.
+pub struct NoteOutput {
+    conn: MidiOutputConnection,
+    sounding: Vec<u8>,
+}
+
+impl NoteOutput {
+    // opens the output named in the config, if there is one and it's plugged in
+    pub fn open() -> Result<Option<NoteOutput>, Box<dyn Error>> {
+        let preferred = match Config::load()?.midi_out {
+            Some(i) => i,
+            None => return Ok(None),
+        };
+
+        let midi_out = MidiOutput::new("rustykeys output")?;
+
+        match find_port(&midi_out, &preferred) {
+            Some(i) => {
+                let port_name = midi_out.port_name(i)?;
+                let conn = midi_out.connect(i, "rustykeys-output")?;
+                println!("Sending prompts to '{}'", port_name);
+                Ok(Some(NoteOutput{conn, sounding: vec!()}))
+            },
+            None => {
+                println!("No MIDI output matching '{}' found, prompts won't be sent", preferred);
+                Ok(None)
+            }
+        }
+    }
+
+    fn send(&mut self, message: &[u8]) {
+        // a dropped prompt isn't worth stopping the exercise for
+        let _ = self.conn.send(message);
+    }
+
+    // holds `keys` down until cleared, lighting them up on keyboards that can
+    pub fn show(&mut self, keys: &[u8]) {
+        self.clear();
+
+        for key in keys {
+            self.send(&[NOTE_ON, *key, VELOCITY]);
+            self.sounding.push(*key);
+        }
+    }
+
+    pub fn clear(&mut self) {
+        for key in self.sounding.drain(..).collect::<Vec<u8>>() {
+            self.send(&[NOTE_OFF, key, 0]);
+        }
+    }
+
+    // sounds `keys` together for `millis`, blocking until they're let go
+    pub fn play_chord(&mut self, keys: &[u8], millis: u64) {
+        self.show(keys);
+        thread::sleep(Duration::from_millis(millis));
+        self.clear();
+    }
+
+    // plays `keys` one after another, each for `note_millis`
+    pub fn play(&mut self, keys: &[u8], note_millis: u64) {
+        self.clear();
+
+        for key in keys {
+            self.send(&[NOTE_ON, *key, VELOCITY]);
+            thread::sleep(Duration::from_millis(note_millis));
+            self.send(&[NOTE_OFF, *key, 0]);
+        }
+    }
+}
+
+impl Drop for NoteOutput {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
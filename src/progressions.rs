@@ -0,0 +1,242 @@
+use std::error::Error;
+use dialoguer::{theme::ColorfulTheme, Select};
+
+use crate::midi::midi_connect;
+use crate::repetition::Deck;
+use crate::history::Session;
+use crate::chords::{select_hand, practice_chords, ChordPrompt};
+use crate::transitions::closest_inversion;
+use crate::utils::{
+    types::{Hand, ChordType, Chord},
+    constants::NOTE_NAMES,
+};
+
+pub const PROGRESSIONS: &'static [&'static [&'static str]] = &[
+    &["I", "IV", "V", "I"],
+    &["ii", "V", "I"],
+    &["I", "vi", "IV", "V"],
+    &["I", "V", "vi", "IV"],
+    &["vi", "IV", "I", "V"],
+    &["I", "vi", "ii", "V"],
+    &["ii7", "V7", "Imaj7"],
+    &["i", "iv", "V", "i"],
+    &["i", "bVII", "bVI", "V"]];
+
+const DEGREES: &'static [(&'static str, usize)] = &[
+    ("VII", 11),
+    ("III", 4),
+    ("VI", 9),
+    ("IV", 5),
+    ("II", 2),
+    ("V", 7),
+    ("I", 0)];
+
+// major keys written with flats, by the tonic's pitch class
+const FLAT_KEYS: &'static [usize] = &[1, 3, 5, 8, 10];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum VoiceLeading {
+    AnyInversion,
+    RootPosition,
+    Closest,
+}
+
+// Parses a Roman numeral such as "V7", "ii", "bVII", "viio" or "V/V" into semitones above the
+// tonic and a chord type. Upper case numerals are major, lower case minor.
+pub fn parse_numeral(numeral: &str) -> Option<(usize, ChordType)> {
+    // a secondary chord is built on the degree after the slash rather than on the tonic
+    if let Some(i) = numeral.find('/') {
+        let (semitones, chord_type) = parse_numeral(&numeral[..i])?;
+        let (degree, _) = parse_numeral(&numeral[i + 1..])?;
+        return Some(((semitones + degree) % NOTE_NAMES.len(), chord_type));
+    }
+
+    let mut rest = numeral;
+    let mut offset: i32 = 0;
+
+    while let Some(c) = rest.chars().next() {
+        match c {
+            'b' => offset -= 1,
+            '#' => offset += 1,
+            _ => break,
+        }
+        rest = &rest[1..];
+    }
+
+    let (degree, semitones) = DEGREES.iter().find(|x| rest.to_uppercase().starts_with(x.0))?;
+    let major = rest[..degree.len()].chars().all(|x| x.is_uppercase());
+
+    let chord_type = match (&rest[degree.len()..], major) {
+        ("", true) => ChordType::Major,
+        ("", false) => ChordType::Minor,
+        ("o", _) => ChordType::Diminished,
+        ("+", _) => ChordType::Augmented,
+        ("7", true) => ChordType::DominantSeventh,
+        ("7", false) => ChordType::MinorSeventh,
+        ("maj7", _) => ChordType::MajorSeventh,
+        ("sus2", _) => ChordType::SusTwo,
+        ("sus4", _) => ChordType::SusFour,
+        _ => return None,
+    };
+
+    Some(((*semitones as i32 + offset).rem_euclid(NOTE_NAMES.len() as i32) as usize, chord_type))
+}
+
+fn spell(pitch_class: usize, flat: bool) -> &'static str {
+    let names = NOTE_NAMES[pitch_class % NOTE_NAMES.len()];
+
+    match flat {
+        true => names[names.len() - 1],
+        false => names[0],
+    }
+}
+
+pub fn key_name(tonic: usize) -> &'static str {
+    spell(tonic, FLAT_KEYS.contains(&tonic))
+}
+
+// Resolves `numeral` in the major key on `tonic` to the root's pitch class, its name as written
+// in that key, and the chord type. Lowered degrees such as bVII are spelled with flats whatever
+// the key.
+pub fn numeral_root(numeral: &str, tonic: usize) -> Option<(usize, &'static str, ChordType)> {
+    let (offset, chord_type) = parse_numeral(numeral)?;
+    let pitch_class = (tonic + offset) % NOTE_NAMES.len();
+
+    Some((pitch_class, spell(pitch_class, FLAT_KEYS.contains(&tonic) || numeral.starts_with('b')), chord_type))
+}
+
+pub fn progression_name(progression: &[&str]) -> String {
+    progression.join("–")
+}
+
+pub fn practice_progressions_launcher() -> Result<(), Box<dyn Error>> {
+    let progression_names: Vec<String> = PROGRESSIONS.iter().map(|x| progression_name(x)).collect();
+
+    let progression_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Pick a progression")
+        .items(progression_names.as_slice())
+        .interact()
+        .unwrap();
+
+    let progression = PROGRESSIONS[progression_selection];
+
+    let mut key_names: Vec<&str> = (0..NOTE_NAMES.len()).map(|x| key_name(x)).collect();
+    key_names.push("All 12 keys");
+
+    let key_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Pick a key")
+        .items(key_names.as_slice())
+        .interact()
+        .unwrap();
+
+    let tonics: Vec<usize> = match key_selection {
+        i if i < NOTE_NAMES.len() => vec![i],
+        _ => (0..NOTE_NAMES.len()).collect(),
+    };
+
+    let voice_leading_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which voicings should be accepted?")
+        .items(&["any inversion", "root position", "closest voice leading"])
+        .interact()
+        .unwrap();
+
+    let voice_leading = match voice_leading_selection {
+        0 => VoiceLeading::AnyInversion,
+        1 => VoiceLeading::RootPosition,
+        _ => VoiceLeading::Closest,
+    };
+
+    let hand = select_hand();
+
+    match midi_connect() {
+        Err(e) => Err(e),
+        Ok(mut conn_in) => {
+            let session = Session::new("progressions", &[
+                ("progression", progression_name(progression)),
+                ("key", key_names[key_selection].to_string()),
+                ("voice_leading", format!("{:?}", voice_leading)),
+                ("hand", format!("{:?}", hand)),
+            ]);
+            let result = practice_chords(&mut conn_in, session, |deck| generate_progressions(progression, &tonics, voice_leading, hand, deck));
+            conn_in.close();
+            result
+        }
+    }
+}
+
+fn progression_prompts(progression: &[&str], tonic: usize, voice_leading: VoiceLeading, hand: Hand) -> Vec<ChordPrompt> {
+    let mut prompts: Vec<ChordPrompt> = vec!();
+
+    for (step, numeral) in progression.iter().enumerate() {
+        let (_, root, chord_type) = match numeral_root(numeral, tonic) {
+            Some(i) => i,
+            None => continue,
+        };
+
+        let inversion = match (voice_leading, prompts.last()) {
+            (VoiceLeading::Closest, Some(i)) => closest_inversion(&i.chord, root, chord_type),
+            _ => 0,
+        };
+
+        let mut prompt = ChordPrompt::new(Chord{root: root.to_string(), chord_type, inversion, octave: None}, hand);
+        prompt.numeral = Some(format!("{} in {}", numeral, key_name(tonic)));
+        prompt.any_inversion = voice_leading == VoiceLeading::AnyInversion;
+        // keyed by the sharp spelling so the card doesn't depend on how the key is displayed
+        prompt.key = format!("progression:{}:{}:{}:{:?}:{:?}", progression_name(progression), NOTE_NAMES[tonic][0], step, voice_leading, hand);
+
+        prompts.push(prompt);
+    }
+
+    prompts
+}
+
+fn generate_progressions(progression: &[&str], tonics: &[usize], voice_leading: VoiceLeading, hand: Hand, deck: &Deck) -> Vec<ChordPrompt> {
+    let mut keys: Vec<Vec<ChordPrompt>> = tonics.iter().map(|x| progression_prompts(progression, *x, voice_leading, hand)).collect();
+
+    // keys containing a chord that's due come first
+    deck.sort_by_keys(&mut keys, |x| x.iter().map(|y| y.key.clone()).collect());
+
+    keys.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_numerals() {
+        assert_eq!(parse_numeral("I"), Some((0, ChordType::Major)));
+        assert_eq!(parse_numeral("ii"), Some((2, ChordType::Minor)));
+        assert_eq!(parse_numeral("IV"), Some((5, ChordType::Major)));
+        assert_eq!(parse_numeral("V7"), Some((7, ChordType::DominantSeventh)));
+        assert_eq!(parse_numeral("vi7"), Some((9, ChordType::MinorSeventh)));
+        assert_eq!(parse_numeral("Imaj7"), Some((0, ChordType::MajorSeventh)));
+        assert_eq!(parse_numeral("viio"), Some((11, ChordType::Diminished)));
+        assert_eq!(parse_numeral("III+"), Some((4, ChordType::Augmented)));
+        assert_eq!(parse_numeral("Vsus4"), Some((7, ChordType::SusFour)));
+    }
+
+    #[test]
+    fn parses_accidentals() {
+        assert_eq!(parse_numeral("bVII"), Some((10, ChordType::Major)));
+        assert_eq!(parse_numeral("bIII"), Some((3, ChordType::Major)));
+        assert_eq!(parse_numeral("#iv"), Some((6, ChordType::Minor)));
+        // flattening the tonic wraps round to the leading note
+        assert_eq!(parse_numeral("bI"), Some((11, ChordType::Major)));
+    }
+
+    #[test]
+    fn parses_secondary_chords() {
+        assert_eq!(parse_numeral("V/V"), Some((2, ChordType::Major)));
+        assert_eq!(parse_numeral("V7/vi"), Some((4, ChordType::DominantSeventh)));
+        assert_eq!(parse_numeral("viio/V"), Some((6, ChordType::Diminished)));
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        assert_eq!(parse_numeral(""), None);
+        assert_eq!(parse_numeral("X"), None);
+        assert_eq!(parse_numeral("V9"), None);
+        assert_eq!(parse_numeral("V/"), None);
+    }
+}
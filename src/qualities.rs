@@ -0,0 +1,202 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+use rand::{thread_rng, Rng};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
+use termion::color;
+
+use crate::midi::{midi_connect, NoteInput};
+use crate::midi_out::NoteOutput;
+use crate::synth::Synth;
+use crate::repetition::Deck;
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+use crate::chords::{chord_keys, identify_chord, select_answer, Answer, CHORD_TYPES};
+use crate::utils::{
+    music::pitch_class,
+    types::{Hand, ChordType, Chord},
+    constants::NOTE_NAMES,
+};
+
+const CHORD_MILLIS: u64 = 1500;
+const SLOW_QUALITY_MILLIS: u64 = 5000;
+
+const TRIADS: &'static [ChordType] = &[ChordType::Major, ChordType::Minor, ChordType::Diminished, ChordType::Augmented];
+const SUS: &'static [ChordType] = &[ChordType::SusTwo, ChordType::SusFour];
+
+pub fn practice_qualities_launcher() -> Result<(), Box<dyn Error>> {
+    let level = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which chords?")
+        .items(&["triads", "triads and sus chords", "everything, sevenths included"])
+        .default(0)
+        .interact()
+        .unwrap();
+
+    let qualities: Vec<(&str, ChordType)> = CHORD_TYPES.iter()
+        .filter(|x| match level {
+            0 => TRIADS.contains(&x.1),
+            1 => TRIADS.contains(&x.1) || SUS.contains(&x.1),
+            _ => true,
+        })
+        .cloned()
+        .collect();
+
+    let inverted = Confirmation::new()
+        .with_text("Would you like inverted chords too?")
+        .interact()
+        .unwrap();
+
+    match select_answer() {
+        Answer::Menu => practice_qualities(None, &qualities, inverted),
+        Answer::Keyboard => match midi_connect() {
+            Err(e) => Err(e),
+            Ok(mut conn_in) => {
+                let result = practice_qualities(Some(&mut conn_in), &qualities, inverted);
+                conn_in.close();
+                result
+            }
+        },
+    }
+}
+
+fn quality_key(chord_type: ChordType, inverted: bool) -> String {
+    format!("chord-quality:{:?}:{}", chord_type, inverted)
+}
+
+// number of keys that aren't part of `chord_type` built on the pitch class `root`
+fn notes_outside(keys: &[u8], root: usize, chord_type: ChordType) -> usize {
+    let positions = chord_type.positions();
+
+    keys.iter()
+        .filter(|x| !positions.contains(&(((pitch_class(**x) + NOTE_NAMES.len() - root) % NOTE_NAMES.len()) as u8)))
+        .count()
+}
+
+// Whether `keys` are exactly a `chord_type` chord on any root and in any inversion. Unlike
+// identify_chord this doesn't have to pick between chords sharing the same notes, such as sus2
+// and the sus4 a fifth above.
+fn has_shape(keys: &[u8], chord_type: ChordType) -> bool {
+    let pitch_classes: Vec<usize> = keys.iter().map(|x| pitch_class(*x)).collect();
+
+    pitch_classes.iter().any(|root| notes_outside(keys, *root, chord_type) == 0
+        && chord_type.positions().iter().all(|x| pitch_classes.contains(&((root + *x as usize) % NOTE_NAMES.len()))))
+}
+
+// wrong notes counted from whichever played root fits best, as any root is allowed
+fn wrong_notes_any_root(keys: &[u8], chord_type: ChordType) -> usize {
+    keys.iter()
+        .map(|x| notes_outside(keys, pitch_class(*x), chord_type))
+        .min()
+        .unwrap_or(0)
+}
+
+fn practice_qualities(mut input: Option<&mut NoteInput>, qualities: &[(&str, ChordType)], inverted: bool) -> Result<(), Box<dyn Error>> {
+    let mut rng = thread_rng();
+    let mut deck = Deck::load()?;
+    let mut output = NoteOutput::open()?;
+    let synth = match output {
+        Some(_) => None,
+        None => Some(Synth::new()?),
+    };
+    let syn = synth.as_ref().map(|x| x.handle());
+    let names: Vec<&str> = qualities.iter().map(|x| x.0).collect();
+    let mut session = Session::new("chord_qualities", &[
+        ("qualities", names.join(";")),
+        ("inverted", inverted.to_string()),
+        ("answer", if input.is_some() { "keyboard" } else { "menu" }.to_string()),
+    ]);
+
+    let mut items = names.clone();
+    items.push("hear it again");
+
+    let mut replay = true;
+
+    while replay {
+        let mut prompts: Vec<(&str, ChordType)> = qualities.to_vec();
+        deck.sort(&mut prompts, |x| quality_key(x.1, inverted));
+
+        let mut round = Round::default();
+
+        for (name, chord_type) in prompts.iter() {
+            let inversion = match inverted {
+                true => rng.gen_range(0, chord_type.positions().len()),
+                false => 0,
+            };
+            let root = NOTE_NAMES[rng.gen_range(0, NOTE_NAMES.len())][0];
+            let chord = Chord{root: root.to_string(), chord_type: *chord_type, inversion, octave: None};
+            let keys = chord_keys(&chord, Hand::Right);
+
+            let mut play = || match (output.as_mut(), syn.as_ref()) {
+                (Some(output), _) => output.play_chord(&keys, CHORD_MILLIS),
+                (None, Some(syn)) => syn.play(&keys, CHORD_MILLIS),
+                (None, None) => {},
+            };
+
+            play();
+
+            let mut attempt = Attempt::new(name.to_string());
+
+            match input.as_mut() {
+                None => loop {
+                    let selection = Select::with_theme(&ColorfulTheme::default())
+                        .with_prompt("What kind of chord was this?")
+                        .items(&items)
+                        .interact()
+                        .unwrap();
+
+                    if selection == qualities.len() {
+                        play();
+                    }
+                    // some chords are another quality inverted, like Csus2 and Gsus4, so naming
+                    // what was heard counts
+                    else if has_shape(&keys, qualities[selection].1) {
+                        println!("{}Correct! It was {}{}", color::Fg(color::Green), chord, color::Fg(color::Reset));
+                        attempt.finish(Instant::now());
+                        break;
+                    }
+                    else {
+                        attempt.miss(0);
+                        println!("{}Not {}{}", color::Fg(color::Red), items[selection], color::Fg(color::Reset));
+                    }
+                },
+                Some(input) => {
+                    println!("Play back the chord you heard, from any root");
+
+                    loop {
+                        let (keys, pressed) = input.next_state()?;
+
+                        if has_shape(&keys, *chord_type) {
+                            println!("{}Correct! It was {}{}", color::Fg(color::Green), chord, color::Fg(color::Reset));
+                            attempt.finish(pressed);
+                            break;
+                        }
+
+                        attempt.miss(wrong_notes_any_root(&keys, *chord_type));
+
+                        match identify_chord(&keys) {
+                            Some(i) => println!("{}That's {}, try again{}", color::Fg(color::Red), i, color::Fg(color::Reset)),
+                            None => println!("{}unrecognised chord, try again{}", color::Fg(color::Red), color::Fg(color::Reset)),
+                        }
+                    }
+                },
+            }
+
+            deck.review(&quality_key(*chord_type, inverted), attempt.quality(Duration::from_millis(SLOW_QUALITY_MILLIS)));
+            deck.save()?;
+            round.push(attempt);
+        }
+
+        round.print_summary();
+        session.record(&round)?;
+
+        replay = Confirmation::new()
+            .with_text("Would you like to practice again?")
+            .interact()
+            .unwrap();
+    }
+
+    if let Some(synth) = synth {
+        synth.close()?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use rand::{thread_rng, Rng, rngs::ThreadRng, seq::SliceRandom};
+use hound::{WavSpec, WavWriter, SampleFormat};
+
+use crate::synth::Recording;
+use crate::chords::{chord_keys, CHORD_TYPES};
+use crate::scales::scale_keys;
+use crate::intervals::{interval_name, Naming, OCTAVE};
+use crate::utils::{
+    constants::NOTE_NAMES,
+    types::{Chord, Hand, Mode},
+};
+
+pub const EXERCISES: &'static [&'static str] = &["intervals", "chords", "scales"];
+
+// interval notes are spaced as in the live exercise
+const INTERVAL_NOTE_MILLIS: u64 = 1000;
+const INTERVAL_GAP_MILLIS: u64 = 50;
+const CHORD_MILLIS: u64 = 2000;
+const SCALE_NOTE_MILLIS: u64 = 400;
+
+// a short high blip marking where a teacher can record the spoken answer
+const ANSWER_KEY: u8 = 96;
+const ANSWER_TONE_MILLIS: u64 = 150;
+
+// lets the last note ring out rather than being cut off
+const TAIL_MILLIS: u64 = 1000;
+
+const MODES: &'static [Mode] = &[Mode::MAJOR, Mode::MINOR, Mode::BLUES];
+
+pub struct RenderOptions {
+    pub count: usize,
+    pub gap_millis: u64,
+    pub answer_tones: bool,
+    pub random_root: bool,
+}
+
+fn root(rng: &mut ThreadRng, random_root: bool) -> usize {
+    match random_root {
+        true => rng.gen_range(0, NOTE_NAMES.len()),
+        false => 0,
+    }
+}
+
+// sounds one prompt and returns its answer
+fn render_prompt(recording: &mut Recording, exercise: &str, rng: &mut ThreadRng, random_root: bool) -> Result<String, Box<dyn Error>> {
+    let root_index = root(rng, random_root);
+    let root_name = NOTE_NAMES[root_index][0];
+
+    match exercise {
+        "intervals" => {
+            let interval = rng.gen_range(0, OCTAVE + 1);
+            let root_key = 48 + root_index as u8;
+
+            for key in [root_key, root_key + interval as u8].iter() {
+                recording.play(&[*key], INTERVAL_NOTE_MILLIS);
+                recording.rest(INTERVAL_GAP_MILLIS);
+            }
+
+            Ok(interval_name(interval, Naming::Compound))
+        },
+        "chords" => {
+            let (_, chord_type) = CHORD_TYPES.choose(rng).unwrap();
+            let chord = Chord{root: root_name.to_string(), chord_type: *chord_type, inversion: 0, octave: None};
+
+            recording.play(&chord_keys(&chord, Hand::Right), CHORD_MILLIS);
+
+            Ok(chord.to_string())
+        },
+        "scales" => {
+            let mode = *MODES.choose(rng).unwrap();
+
+            for key in scale_keys(mode, root_name) {
+                recording.play(&[key], SCALE_NOTE_MILLIS);
+            }
+
+            Ok(format!("{} {}", root_name, mode))
+        },
+        _ => Err(format!("can't render '{}', pick one of {}", exercise, EXERCISES.join(", ")).into()),
+    }
+}
+
+fn timestamp(millis: u64) -> String {
+    format!("{}:{:02}", millis / 60000, millis / 1000 % 60)
+}
+
+fn write_wav(path: &Path, recording: &Recording) -> Result<(), Box<dyn Error>> {
+    let spec = WavSpec {
+        channels: 1,
+        sample_rate: recording.sample_rate(),
+        bits_per_sample: 16,
+        sample_format: SampleFormat::Int,
+    };
+
+    let mut writer = WavWriter::create(path, spec)?;
+
+    for sample in recording.samples() {
+        writer.write_sample((sample * f32::from(i16::MAX)) as i16)?;
+    }
+
+    writer.finalize()?;
+
+    Ok(())
+}
+
+// Renders `count` prompts into a WAV file, each followed by a gap to answer in and optionally a
+// tone and a second gap for the answer to be dubbed over. The answers are written alongside as a
+// text file with the time each prompt starts at.
+pub fn render(exercise: &str, path: &Path, options: &RenderOptions) -> Result<(), Box<dyn Error>> {
+    let mut rng = thread_rng();
+    let mut recording = Recording::new()?;
+    let mut answers: Vec<String> = vec!();
+
+    for i in 0..options.count {
+        let start = recording.millis();
+        let answer = render_prompt(&mut recording, exercise, &mut rng, options.random_root)?;
+
+        recording.rest(options.gap_millis);
+
+        if options.answer_tones {
+            recording.play(&[ANSWER_KEY], ANSWER_TONE_MILLIS);
+            recording.rest(options.gap_millis);
+        }
+
+        answers.push(format!("{}. [{}] {}", i + 1, timestamp(start), answer));
+    }
+
+    recording.rest(TAIL_MILLIS);
+    write_wav(path, &recording)?;
+
+    let answers_path = path.with_extension("txt");
+    fs::write(&answers_path, answers.join("\n") + "\n")?;
+
+    println!("Wrote {} of {} to {}, answers in {}",
+        timestamp(recording.millis()), exercise, path.display(), answers_path.display());
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Config;
+
+    #[test]
+    fn renders_a_chord_prompt() {
+        let mut recording = Recording::with_config(&Config::default());
+        let answer = render_prompt(&mut recording, "chords", &mut thread_rng(), false).unwrap();
+
+        assert!(answer.starts_with('C'));
+        assert_eq!(recording.samples().len(), (CHORD_MILLIS * u64::from(recording.sample_rate()) / 1000) as usize);
+        assert!(recording.samples().iter().any(|x| x.abs() > 0.01));
+    }
+
+    #[test]
+    fn rejects_unknown_exercises() {
+        let mut recording = Recording::with_config(&Config::default());
+
+        assert!(render_prompt(&mut recording, "arpeggios", &mut thread_rng(), false).is_err());
+        assert!(recording.samples().is_empty());
+    }
+}
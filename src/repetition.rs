@@ -0,0 +1,186 @@
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter};
+use std::path::PathBuf;
+use chrono::Utc;
+use rand::{thread_rng, seq::SliceRandom};
+use serde::{Serialize, Deserialize};
+
+use crate::utils::paths::data_dir;
+
+const SECONDS_PER_DAY: i64 = 60 * 60 * 24;
+const MIN_EASINESS: f64 = 1.3;
+
+// SM-2 memory state for a single drill item
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Card {
+    pub easiness: f64,
+    pub repetitions: u32,
+    pub interval_days: u32,
+    pub lapses: u32,
+    pub due: i64,
+}
+
+impl Default for Card {
+    fn default() -> Self {
+        Card {
+            easiness: 2.5,
+            repetitions: 0,
+            interval_days: 0,
+            lapses: 0,
+            due: 0,
+        }
+    }
+}
+
+impl Card {
+    // quality runs from 0 (complete blackout) to 5 (perfect response)
+    pub fn review(&mut self, quality: u8, now: i64) {
+        let q = f64::from(5 - quality.min(5));
+
+        if quality < 3 {
+            // failed items restart their schedule and stay due, so the next session opens with them
+            self.repetitions = 0;
+            self.interval_days = 0;
+            self.lapses += 1;
+        }
+        else {
+            self.interval_days = match self.repetitions {
+                0 => 1,
+                1 => 6,
+                _ => (f64::from(self.interval_days) * self.easiness).round() as u32,
+            };
+            self.repetitions += 1;
+        }
+
+        self.easiness = (self.easiness + 0.1 - q * (0.08 + q * 0.02)).max(MIN_EASINESS);
+        self.due = now + i64::from(self.interval_days) * SECONDS_PER_DAY;
+    }
+}
+
+#[derive(Default, Serialize, Deserialize)]
+pub struct Deck {
+    cards: HashMap<String, Card>,
+    #[serde(skip)]
+    path: PathBuf,
+}
+
+impl Deck {
+    pub fn load() -> Result<Deck, Box<dyn Error>> {
+        let path = data_dir()?.join("memory.json");
+
+        if !path.exists() {
+            return Ok(Deck{cards: HashMap::new(), path});
+        }
+
+        let mut deck: Deck = serde_json::from_reader(BufReader::new(File::open(&path)?))?;
+        deck.path = path;
+
+        Ok(deck)
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&self.path)?), self)?;
+
+        Ok(())
+    }
+
+    pub fn review(&mut self, key: &str, quality: u8) {
+        self.cards.entry(key.to_string()).or_default().review(quality, Utc::now().timestamp());
+    }
+
+    // Orders items so that due reviews come first (weakest first), then unseen items, then
+    // everything else by due date. Ties are shuffled so rounds still vary.
+    pub fn sort<T, F>(&self, items: &mut Vec<T>, key: F) where F: Fn(&T) -> String {
+        self.sort_by_keys(items, |x| vec![key(x)]);
+    }
+
+    // As sort, for items made up of several cards; each item ranks as its most urgent card
+    pub fn sort_by_keys<T, F>(&self, items: &mut Vec<T>, keys: F) where F: Fn(&T) -> Vec<String> {
+        let now = Utc::now().timestamp();
+
+        let rank = |key: &String| match self.cards.get(key) {
+            Some(card) if card.due <= now => (0, card.easiness, card.due),
+            None => (1, 0.0, 0),
+            Some(card) => (2, 0.0, card.due),
+        };
+
+        let item_rank = |item: &T| keys(item).iter()
+            .map(|x| rank(x))
+            .min_by(|a, b| a.partial_cmp(b).unwrap())
+            .unwrap_or((1, 0.0, 0));
+
+        items.shuffle(&mut thread_rng());
+        items.sort_by(|a, b| item_rank(a).partial_cmp(&item_rank(b)).unwrap());
+    }
+}
+
+// SM-2 quality for an item that was eventually answered correctly after a number of mistakes
+pub fn quality(mistakes: usize) -> u8 {
+    match mistakes {
+        0 => 5,
+        1 => 3,
+        2 => 2,
+        _ => 1,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn good_answers_space_reviews_out() {
+        let mut card = Card::default();
+
+        card.review(5, 0);
+        assert_eq!((card.repetitions, card.interval_days, card.due), (1, 1, SECONDS_PER_DAY));
+        assert!((card.easiness - 2.6).abs() < 1e-9);
+
+        card.review(5, 0);
+        assert_eq!(card.interval_days, 6);
+
+        // from the third review on, the interval grows by the easiness
+        card.review(4, 100);
+        assert_eq!(card.interval_days, 16);
+        assert_eq!(card.due, 100 + 16 * SECONDS_PER_DAY);
+        assert!((card.easiness - 2.7).abs() < 1e-9);
+    }
+
+    #[test]
+    fn lapses_restart_the_schedule() {
+        let mut card = Card::default();
+        card.review(5, 0);
+        card.review(5, 0);
+
+        card.review(1, 1000);
+        assert_eq!((card.repetitions, card.interval_days, card.lapses), (0, 0, 1));
+        assert_eq!(card.due, 1000);
+        assert!((card.easiness - 2.16).abs() < 1e-9);
+    }
+
+    #[test]
+    fn easiness_has_a_floor() {
+        let mut card = Card::default();
+
+        for _ in 0..10 {
+            card.review(0, 0);
+        }
+
+        assert_eq!(card.easiness, MIN_EASINESS);
+        assert_eq!(card.lapses, 10);
+    }
+
+    #[test]
+    fn quality_falls_with_mistakes() {
+        assert_eq!(quality(0), 5);
+        assert_eq!(quality(1), 3);
+        assert!(quality(2) < 3);
+        assert_eq!(quality(10), 1);
+    }
+}
@@ -0,0 +1,412 @@
+use std::error::Error;
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation, Input};
+use termion::color;
+use midly::{Smf, Timing, TrackEventKind, MidiMessage, MetaMessage};
+
+use crate::midi::{midi_connect, NoteInput};
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+use crate::synth::{Synth, SynthHandle};
+use crate::utils::music::note_label;
+
+const DEFAULT_MICROS_PER_BEAT: u32 = 500_000;
+const CLICK_MILLIS: u64 = 30;
+const CLICK_KEY: u8 = 84;
+const ACCENT_KEY: u8 = 91;
+const ON_TIME_MILLIS: i64 = 100;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RiffMode {
+    Wait,
+    Tempo,
+}
+
+// notes that start together on the same tick
+pub struct Step {
+    pub tick: u32,
+    pub keys: Vec<u8>,
+}
+
+pub struct Riff {
+    pub ticks_per_beat: u32,
+    pub beats_per_bar: u32,
+    pub micros_per_beat: u32,
+    pub tracks: Vec<(String, Vec<Step>)>,
+}
+
+impl Riff {
+    pub fn load(path: &str) -> Result<Riff, Box<dyn Error>> {
+        let data = fs::read(path)?;
+        let smf = Smf::parse(&data)?;
+
+        let ppq = match smf.header.timing {
+            Timing::Metrical(i) => u32::from(i.as_int()),
+            Timing::Timecode(_, _) => return Err("SMPTE-timed MIDI files are not supported".into()),
+        };
+
+        let mut beats_per_bar = 4;
+        let mut beat_unit = 4;
+        let mut micros_per_beat = None;
+        let mut tracks = vec!();
+
+        for (i, track) in smf.tracks.iter().enumerate() {
+            let mut name = format!("Track {}", i + 1);
+            let mut tick = 0;
+            let mut steps: Vec<Step> = vec!();
+
+            for event in track.iter() {
+                tick += event.delta.as_int();
+
+                match event.kind {
+                    TrackEventKind::Midi { message: MidiMessage::NoteOn { key, vel }, .. } if vel.as_int() > 0 => {
+                        match steps.last_mut() {
+                            Some(step) if step.tick == tick => step.keys.push(key.as_int()),
+                            _ => steps.push(Step{tick, keys: vec![key.as_int()]}),
+                        }
+                    },
+                    TrackEventKind::Meta(MetaMessage::TrackName(i)) => name = String::from_utf8_lossy(i).to_string(),
+                    // only the first tempo and time signature are used
+                    TrackEventKind::Meta(MetaMessage::Tempo(i)) if micros_per_beat.is_none() => micros_per_beat = Some(i.as_int()),
+                    TrackEventKind::Meta(MetaMessage::TimeSignature(numerator, denominator, _, _)) if tick == 0 => {
+                        beats_per_bar = u32::from(numerator);
+                        // the denominator is a power of two, so a broken file can ask for a huge one
+                        beat_unit = match 1u32.checked_shl(u32::from(denominator)) {
+                            Some(i) => i,
+                            None => return Err(format!("unsupported time signature {}/2^{}", numerator, denominator).into()),
+                        };
+                    },
+                    _ => {},
+                }
+            }
+
+            if !steps.is_empty() {
+                for step in steps.iter_mut() {
+                    step.keys.sort();
+                    step.keys.dedup();
+                }
+
+                tracks.push((name, steps));
+            }
+        }
+
+        if tracks.is_empty() {
+            return Err("no notes found in MIDI file".into());
+        }
+
+        let ticks_per_beat = ppq * 4 / beat_unit;
+
+        // bars and beats are found by dividing by these
+        if ticks_per_beat == 0 {
+            return Err("the MIDI file's timing is too coarse for its time signature".into());
+        }
+
+        if beats_per_bar == 0 {
+            return Err("the MIDI file's time signature has no beats in a bar".into());
+        }
+
+        Ok(Riff {
+            ticks_per_beat,
+            beats_per_bar,
+            micros_per_beat: micros_per_beat.unwrap_or(DEFAULT_MICROS_PER_BEAT),
+            tracks,
+        })
+    }
+
+    pub fn ticks_per_bar(&self) -> u32 {
+        self.ticks_per_beat * self.beats_per_bar
+    }
+
+    // 1-based bar and beat
+    pub fn position(&self, tick: u32) -> (u32, u32) {
+        (tick / self.ticks_per_bar() + 1, tick % self.ticks_per_bar() / self.ticks_per_beat + 1)
+    }
+}
+
+pub fn practice_riffs_launcher() -> Result<(), Box<dyn Error>> {
+    let path: String = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Path to a MIDI file")
+        .interact()?;
+
+    let riff = Riff::load(path.trim())?;
+
+    let track_names: Vec<String> = riff.tracks.iter().map(|x| format!("{} ({} notes)", x.0, x.1.len())).collect();
+
+    let track = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Pick a track")
+        .items(track_names.as_slice())
+        .interact()
+        .unwrap();
+
+    let bars = riff.position(riff.tracks[track].1.last().unwrap().tick).0;
+
+    // bars past the end are turned into ticks, so they're kept within the track
+    let first_bar: u32 = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(&format!("First bar (1-{})", bars))
+        .default(1)
+        .interact()?;
+    let first_bar = first_bar.max(1).min(bars);
+
+    let last_bar: u32 = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt(&format!("Last bar ({}-{})", first_bar, bars))
+        .default(bars)
+        .interact()?;
+    let last_bar = last_bar.min(bars);
+
+    if last_bar < first_bar {
+        return Err(format!("the last bar ({}) comes before the first ({})", last_bar, first_bar).into());
+    }
+
+    let mode_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("How would you like to practice?")
+        .items(&["wait mode", "tempo mode"])
+        .interact()
+        .unwrap();
+
+    let mode = match mode_selection {
+        0 => RiffMode::Wait,
+        _ => RiffMode::Tempo,
+    };
+
+    let tempo_percent: u32 = match mode {
+        RiffMode::Tempo => Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Tempo, as a percentage of the original")
+            .default(100)
+            .interact()?,
+        RiffMode::Wait => 100,
+    };
+
+    match midi_connect() {
+        Err(e) => Err(e),
+        Ok(mut conn_in) => {
+            let result = practice_riff(&mut conn_in, &riff, &path, track, (first_bar, last_bar), mode, tempo_percent);
+            conn_in.close();
+            result
+        }
+    }
+}
+
+fn step_label(riff: &Riff, step: &Step) -> String {
+    let (bar, beat) = riff.position(step.tick);
+    let notes: Vec<String> = step.keys.iter().map(|x| note_label(*x)).collect();
+
+    format!("bar {} beat {}: {}", bar, beat, notes.join(" "))
+}
+
+fn practice_riff(input: &mut NoteInput, riff: &Riff, path: &str, track: usize, bars: (u32, u32), mode: RiffMode, tempo_percent: u32) -> Result<(), Box<dyn Error>> {
+    let start_tick = (bars.0.max(1) - 1) * riff.ticks_per_bar();
+    let end_tick = bars.1 * riff.ticks_per_bar();
+    let steps: Vec<&Step> = riff.tracks[track].1.iter().filter(|x| x.tick >= start_tick && x.tick < end_tick).collect();
+
+    if steps.is_empty() {
+        return Err("no notes in the chosen bars".into());
+    }
+
+    let mut session = Session::new("riffs", &[
+        ("file", path.to_string()),
+        ("track", riff.tracks[track].0.clone()),
+        ("bars", format!("{}-{}", bars.0, bars.1)),
+        ("mode", format!("{:?}", mode)),
+        ("tempo_percent", tempo_percent.to_string()),
+    ]);
+    let mut replay = true;
+
+    while replay {
+        let listen = Confirmation::new()
+            .with_text("Would you like to hear the riff first?")
+            .interact()
+            .unwrap();
+
+        if listen {
+            match Synth::new() {
+                Ok(synth) => {
+                    play_riff(synth.handle(), riff, &steps, tempo_percent);
+                    synth.close()?;
+                },
+                Err(e) => println!("No audio output, so the riff can't be played: {}", e),
+            }
+        }
+
+        let round = match mode {
+            RiffMode::Wait => practice_wait(input, riff, &steps)?,
+            RiffMode::Tempo => practice_tempo(input, riff, &steps, start_tick, tempo_percent)?,
+        };
+
+        round.print_summary();
+        session.record(&round)?;
+
+        replay = Confirmation::new()
+            .with_text("Would you like to practice again?")
+            .interact()
+            .unwrap();
+    }
+
+    Ok(())
+}
+
+fn micros_per_tick(riff: &Riff, tempo_percent: u32) -> f64 {
+    f64::from(riff.micros_per_beat) / f64::from(riff.ticks_per_beat) * 100.0 / f64::from(tempo_percent.max(1))
+}
+
+// plays the steps as a reference, each held until the next one starts and the last for a beat
+fn play_riff(syn: SynthHandle, riff: &Riff, steps: &[&Step], tempo_percent: u32) {
+    let micros_per_tick = micros_per_tick(riff, tempo_percent);
+
+    for (i, step) in steps.iter().enumerate() {
+        let ticks = match steps.get(i + 1) {
+            Some(next) => next.tick - step.tick,
+            None => riff.ticks_per_beat,
+        };
+
+        syn.play(&step.keys, (f64::from(ticks) * micros_per_tick / 1000.0) as u64);
+    }
+}
+
+// the exercise only moves on once exactly the right notes are down, though notes still held
+// from the previous step are allowed so legato playing isn't punished
+fn practice_wait(input: &mut NoteInput, riff: &Riff, steps: &[&Step]) -> Result<Round, Box<dyn Error>> {
+    let mut round = Round::default();
+    let mut previous: &[u8] = &[];
+
+    for step in steps.iter() {
+        println!("Play {}", step_label(riff, step));
+        let mut attempt = Attempt::new(step_label(riff, step));
+
+        loop {
+            let (keys_down, pressed) = input.next_state()?;
+            let wrong: Vec<&u8> = keys_down.iter().filter(|x| !step.keys.contains(x) && !previous.contains(x)).collect();
+
+            if wrong.is_empty() && step.keys.iter().all(|x| keys_down.contains(x)) {
+                println!("{}Correct!{}", color::Fg(color::Green), color::Fg(color::Reset));
+                attempt.finish(pressed);
+                break;
+            }
+            else if !wrong.is_empty() {
+                attempt.miss(wrong.len());
+                let names: Vec<String> = wrong.iter().map(|x| note_label(**x)).collect();
+                println!("{}Not {}{}", color::Fg(color::Red), names.join(" "), color::Fg(color::Reset));
+            }
+        }
+
+        previous = &step.keys;
+        round.push(attempt);
+    }
+
+    Ok(round)
+}
+
+fn start_metronome(syn: SynthHandle, beat: Duration, beats_per_bar: u32, first_beat: Instant, running: Arc<AtomicBool>) {
+    thread::spawn(move || {
+        let mut count = 0;
+
+        while running.load(Ordering::SeqCst) {
+            let next = first_beat + beat * count;
+            let now = Instant::now();
+
+            if next > now {
+                thread::sleep(next - now);
+            }
+
+            let key = if count % beats_per_bar == 0 { ACCENT_KEY } else { CLICK_KEY };
+            syn.play(&[key], CLICK_MILLIS);
+
+            count += 1;
+        }
+    });
+}
+
+// Steps are expected on the beat after a bar of count-in. Each step is timed from its first note,
+// and is marked missed once it is a beat late.
+fn practice_tempo(input: &mut NoteInput, riff: &Riff, steps: &[&Step], start_tick: u32, tempo_percent: u32) -> Result<Round, Box<dyn Error>> {
+    let mut round = Round::default();
+    let micros_per_tick = micros_per_tick(riff, tempo_percent);
+    let beat = Duration::from_micros((micros_per_tick * f64::from(riff.ticks_per_beat)) as u64);
+    let count_in = beat * riff.beats_per_bar;
+    let running = Arc::new(AtomicBool::new(true));
+
+    let first_beat = Instant::now() + beat;
+    let start = first_beat + count_in;
+
+    println!("Count in: {} beats at {:.0} bpm", riff.beats_per_bar, 60.0 / beat.as_secs_f64());
+
+    // without audio the riff can still be played against the clock
+    let synth = Synth::new().ok();
+    match &synth {
+        Some(i) => start_metronome(i.handle(), beat, riff.beats_per_bar, first_beat, running.clone()),
+        None => println!("No audio output, so there's no metronome"),
+    }
+
+    let mut offsets: Vec<i64> = vec!();
+
+    for step in steps.iter() {
+        let expected = start + Duration::from_micros((f64::from(step.tick - start_tick) * micros_per_tick) as u64);
+        let mut attempt = Attempt::new(step_label(riff, step));
+        let mut pending = step.keys.clone();
+        let mut first_onset: Option<Instant> = None;
+
+        while !pending.is_empty() {
+            let now = Instant::now();
+
+            if now > expected + beat {
+                attempt.miss(pending.len());
+                break;
+            }
+
+            match input.next_event(expected + beat - now)? {
+                Some(event) if event.down => {
+                    match pending.iter().position(|x| *x == event.key) {
+                        Some(i) => {
+                            pending.remove(i);
+                            first_onset.get_or_insert(event.time);
+                        },
+                        None => attempt.wrong_notes += 1,
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        match first_onset {
+            Some(i) if pending.is_empty() => {
+                let offset = if i > expected {
+                    (i - expected).as_millis() as i64
+                }
+                else {
+                    -((expected - i).as_millis() as i64)
+                };
+
+                let colour = if offset.abs() <= ON_TIME_MILLIS { format!("{}", color::Fg(color::Green)) } else { format!("{}", color::Fg(color::Yellow)) };
+                println!("{}{} {:+}ms{}", colour, step_label(riff, step), offset, color::Fg(color::Reset));
+
+                // the summary's time column shows how far off the beat each step was
+                attempt.duration = Some(Duration::from_millis(offset.unsigned_abs()));
+                offsets.push(offset);
+            },
+            _ => {
+                println!("{}{} missed{}", color::Fg(color::Red), step_label(riff, step), color::Fg(color::Reset));
+                attempt.duration = Some(beat);
+            },
+        }
+
+        round.push(attempt);
+    }
+
+    running.store(false, Ordering::SeqCst);
+
+    if let Some(i) = synth {
+        i.close()?;
+    }
+
+    if !offsets.is_empty() {
+        let on_time = offsets.iter().filter(|x| x.abs() <= ON_TIME_MILLIS).count();
+        let mean = offsets.iter().sum::<i64>() / offsets.len() as i64;
+
+        println!("\n{}/{} steps within {}ms of the beat, mean offset {:+}ms", on_time, steps.len(), ON_TIME_MILLIS, mean);
+    }
+
+    Ok(round)
+}
@@ -1,19 +1,24 @@
 use std::error::Error;
-use rand::{thread_rng, seq::SliceRandom};
 use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
 use termion::color;
 use std::io::{stdout, Write};
+use std::time::Duration;
 use ndarray::Array2;
 use num_traits::FromPrimitive;
 
-use crate::midi::midi_connect;
+use crate::midi::{midi_connect, NoteInput};
+use crate::midi_out::NoteOutput;
+use crate::repetition::Deck;
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
 use crate::utils::{
-    music::{note_matches, get_note_name},
-    mutex::{KEYS_DOWN, LAST_KEY_PRESS},
-    constants::{DEBOUNCE_MILLIS, NOTE_NAMES},
+    music::{note_matches, get_note_name, note_index},
+    constants::NOTE_NAMES,
     types::Mode,
 };
 
+const SLOW_SCALE_MILLIS: u64 = 10000;
+
 pub fn practice_scales_launcher() -> Result<(), Box<dyn Error>> {
     let modes: &'static [&'static str] = &[
         "Blues",
@@ -30,8 +35,8 @@ pub fn practice_scales_launcher() -> Result<(), Box<dyn Error>> {
     if let Some(mode) = FromPrimitive::from_usize(mode_selection) {
         match midi_connect() {
             Err(e) => Err(e),
-            Ok(conn_in) => {
-                let result = practice_scales(mode);
+            Ok(mut conn_in) => {
+                let result = practice_scales(&mut conn_in, mode);
                 conn_in.close();
                 result
             }
@@ -41,47 +46,79 @@ pub fn practice_scales_launcher() -> Result<(), Box<dyn Error>> {
     }
 }
 
-fn practice_scales(mode: Mode) -> Result<(), Box<dyn Error>> {
+fn scale_key(mode: Mode, root: &str) -> String {
+    format!("scale:{}:{}", mode, root)
+}
+
+fn practice_scales(input: &mut NoteInput, mode: Mode) -> Result<(), Box<dyn Error>> {
+    let mut deck = Deck::load()?;
+    let mut output = NoteOutput::open()?;
+    let mut session = Session::new("scales", &[("mode", mode.to_string())]);
     let mut replay = true;
 
     while replay {
-        let scales = generate_scales(mode);
+        let scales = generate_scales(mode, &deck);
+        let mut round = Round::default();
 
         for scale in scales.outer_iter() {
+            let mut attempt = Attempt::new(format!("{} {}", scale[0], mode));
+
             print!("{}: ", scale[0]);
             stdout().flush()?;
 
-            for note in scale.iter() {
+            let keys_to_show = scale_keys(mode, &scale[0]);
+            let top = keys_to_show.len() - 1;
+
+            for (i, note) in scale.iter().enumerate() {
+                let mut missed = false;
+
+                // only the next note is shown, so the student's instrument isn't left droning, and
+                // on the way back down the keys going up are shown in reverse
+                if let Some(output) = output.as_mut() {
+                    output.show(&[keys_to_show[i.min(2 * top - i)]]);
+                }
+
                 loop {
-                    let last_key_press = *LAST_KEY_PRESS.lock().unwrap();
-
-                    match last_key_press {
-                        Some(i) => {
-                            if i.elapsed().as_millis() > DEBOUNCE_MILLIS.into() {
-                                *LAST_KEY_PRESS.lock().unwrap() = None;
-
-                                if let Some(i) = KEYS_DOWN.lock().unwrap().last() {
-                                    if note_matches(*i, &note) {
-                                        // delete incorrect input
-                                        print!("{}{}{} ", color::Fg(color::Green), note, color::Fg(color::Reset));
-                                        stdout().flush()?;
-                                        break;
-                                    }
-                                    else {
-                                        print!("{}{}{} ", color::Fg(color::Red), get_note_name(*i), color::Fg(color::Reset));
-                                        stdout().flush()?;
-                                    }
-                                }
+                    let (keys, pressed) = input.next_state()?;
+
+                    if let Some(key) = keys.last() {
+                        if note_matches(*key, &note) {
+                            // delete incorrect input
+                            print!("{}{}{} ", color::Fg(color::Green), note, color::Fg(color::Reset));
+                            stdout().flush()?;
+                            attempt.finish(pressed);
+
+                            if let Some(output) = output.as_mut() {
+                                output.clear();
                             }
+                            break;
+                        }
+                        else {
+                            // a position counts as one wrong attempt however many wrong notes it takes
+                            if missed {
+                                attempt.wrong_notes += 1;
+                            }
+                            else {
+                                attempt.miss(1);
+                                missed = true;
+                            }
+                            print!("{}{}{} ", color::Fg(color::Red), get_note_name(*key), color::Fg(color::Reset));
+                            stdout().flush()?;
                         }
-                        _ => {},
                     }
                 }
             }
 
             println!("");
+
+            deck.review(&scale_key(mode, &scale[0]), attempt.quality(Duration::from_millis(SLOW_SCALE_MILLIS)));
+            deck.save()?;
+            round.push(attempt);
         }
 
+        round.print_summary();
+        session.record(&round)?;
+
         replay = Confirmation::new()
             .with_text("Would you like to practice again?")
             .interact()
@@ -91,10 +128,20 @@ fn practice_scales(mode: Mode) -> Result<(), Box<dyn Error>> {
     Ok(())
 }
 
-fn generate_scales(mode: Mode) -> Array2::<String> {
-    let mut rng = thread_rng();
+// one octave up from the root nearest middle C
+pub fn scale_keys(mode: Mode, root: &str) -> Vec<u8> {
+    let mut keys = vec![60 + note_index(root).unwrap_or(0) as u8];
+
+    for interval in mode.value() {
+        keys.push(keys.last().unwrap() + interval as u8);
+    }
+
+    keys
+}
+
+fn generate_scales(mode: Mode, deck: &Deck) -> Array2::<String> {
     let mut root_indices: Vec<usize> = (0..NOTE_NAMES.len()).collect();
-    root_indices.shuffle(&mut rng);
+    deck.sort(&mut root_indices, |x| scale_key(mode, NOTE_NAMES[*x][0]));
     let intervals = mode.value();
 
     println!("Generating {} scales with intervals {:?}", mode, mode.value());
@@ -0,0 +1,364 @@
+use std::error::Error;
+use std::time::Instant;
+use rand::{thread_rng, seq::SliceRandom};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
+use termion::{clear, color, cursor};
+
+use crate::midi::{midi_connect, NoteInput};
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+use crate::utils::constants::NOTE_NAMES;
+
+const ROUND_LENGTH: usize = 20;
+
+const LETTERS: &'static str = "CDEFGAB";
+const SHARP_ORDER: &'static str = "FCGDAEB";
+const FLAT_ORDER: &'static str = "BEADGCF";
+const NATURALS: [i32; 7] = [0, 2, 4, 5, 7, 9, 11];
+
+// staff positions count diatonic steps from C-1, so middle C is 28 and each line is two steps apart
+const TREBLE_LINES: [i32; 5] = [30, 32, 34, 36, 38];
+const BASS_LINES: [i32; 5] = [18, 20, 22, 24, 26];
+const SHARP_STEPS: [i32; 7] = [38, 35, 39, 36, 33, 37, 34];
+const FLAT_STEPS: [i32; 7] = [34, 37, 33, 36, 32, 35, 31];
+const BASS_OFFSET: i32 = -14;
+
+const KEY_SIG_COLUMN: usize = 3;
+const NOTE_COLUMN: usize = 22;
+const STAFF_WIDTH: usize = 30;
+
+// positive for sharps, negative for flats
+const KEY_SIGNATURES: &'static [(&'static str, i32)] = &[
+    ("C major", 0),
+    ("G major", 1),
+    ("D major", 2),
+    ("A major", 3),
+    ("E major", 4),
+    ("B major", 5),
+    ("F# major", 6),
+    ("F major", -1),
+    ("Bb major", -2),
+    ("Eb major", -3),
+    ("Ab major", -4),
+    ("Db major", -5),
+    ("Gb major", -6)];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Clef {
+    Treble,
+    Bass,
+    Grand,
+}
+
+impl Clef {
+    // MIDI note ranges, on the staff and with up to three ledger lines
+    fn range(&self, ledger_lines: bool) -> (u8, u8) {
+        match (*self, ledger_lines) {
+            (Clef::Treble, false) => (64, 77),
+            (Clef::Treble, true) => (57, 84),
+            (Clef::Bass, false) => (43, 57),
+            (Clef::Bass, true) => (36, 64),
+            (Clef::Grand, false) => (43, 77),
+            (Clef::Grand, true) => (36, 84),
+        }
+    }
+}
+
+pub fn practice_sight_reading_launcher() -> Result<(), Box<dyn Error>> {
+    let clef_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Which clef?")
+        .items(&["treble", "bass", "grand staff"])
+        .interact()
+        .unwrap();
+
+    let clef = match clef_selection {
+        0 => Clef::Treble,
+        1 => Clef::Bass,
+        _ => Clef::Grand,
+    };
+
+    let ledger_lines = Confirmation::new()
+        .with_text("Include notes on ledger lines?")
+        .interact()
+        .unwrap();
+
+    let accidentals = Confirmation::new()
+        .with_text("Include accidentals?")
+        .interact()
+        .unwrap();
+
+    let key_names: Vec<&str> = KEY_SIGNATURES.iter().map(|x| x.0).collect();
+
+    let key_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("Pick a key signature")
+        .items(key_names.as_slice())
+        .interact()
+        .unwrap();
+
+    match midi_connect() {
+        Err(e) => Err(e),
+        Ok(mut conn_in) => {
+            let result = practice_sight_reading(&mut conn_in, clef, ledger_lines, accidentals, key_selection);
+            conn_in.close();
+            result
+        }
+    }
+}
+
+fn altered_letter(letter: char, key_accidentals: i32) -> Option<char> {
+    if key_accidentals > 0 && SHARP_ORDER[..key_accidentals as usize].contains(letter) {
+        Some('#')
+    }
+    else if key_accidentals < 0 && FLAT_ORDER[..(-key_accidentals) as usize].contains(letter) {
+        Some('b')
+    }
+    else {
+        None
+    }
+}
+
+// the letter and alteration `note` has in the key signature, if it's in the key at all
+fn diatonic_spelling(note: u8, key_accidentals: i32) -> Option<(char, Option<char>)> {
+    LETTERS.chars()
+        .map(|x| (x, altered_letter(x, key_accidentals)))
+        .find(|(letter, alteration)| {
+            let natural = NATURALS[LETTERS.find(*letter).unwrap()];
+            (natural + shift(*alteration) - i32::from(note)).rem_euclid(12) == 0
+        })
+}
+
+fn shift(alteration: Option<char>) -> i32 {
+    match alteration {
+        Some('#') => 1,
+        Some('b') => -1,
+        _ => 0,
+    }
+}
+
+// note name, staff step and the accidental that has to be written next to it in this key
+fn spell(note: u8, key_accidentals: i32) -> (String, i32, Option<char>) {
+    // notes in the key are spelled as the signature has them, so F# major has an E# and Gb major a Cb
+    let (letter, alteration) = match diatonic_spelling(note, key_accidentals) {
+        Some(x) => x,
+        None => {
+            let names = NOTE_NAMES[note as usize % NOTE_NAMES.len()];
+            let name = match key_accidentals < 0 && names.len() > 1 {
+                true => names[1],
+                false => names[0],
+            };
+
+            (name.chars().next().unwrap(), name.chars().nth(1))
+        },
+    };
+
+    // E#4 is the key a semitone above E4, so the octave comes from the unaltered letter
+    let octave = (i32::from(note) - shift(alteration)) / 12 - 1;
+    let step = LETTERS.find(letter).unwrap() as i32 + 7 * octave;
+
+    let written = if alteration == altered_letter(letter, key_accidentals) {
+        None
+    }
+    else {
+        match alteration {
+            Some('#') => Some('♯'),
+            Some('b') => Some('♭'),
+            _ => Some('♮'),
+        }
+    };
+
+    let name: String = Some(letter).into_iter().chain(alteration).collect();
+
+    (format!("{}{}", name, octave), step, written)
+}
+
+fn render(clef: Clef, key_accidentals: i32, note: u8) -> Vec<String> {
+    // lines, clef letter, the line the clef names, and where the key signature sits
+    let treble = (&TREBLE_LINES, 'G', TREBLE_LINES[1], 0);
+    let bass = (&BASS_LINES, 'F', BASS_LINES[3], BASS_OFFSET);
+
+    let staves = match clef {
+        Clef::Treble => vec![treble],
+        Clef::Bass => vec![bass],
+        Clef::Grand => vec![treble, bass],
+    };
+
+    // on the grand staff, middle C and above sit on the treble staff
+    let note_staff = match clef {
+        Clef::Grand if note < 60 => &staves[1],
+        _ => &staves[0],
+    };
+
+    let (_, step, accidental) = spell(note, key_accidentals);
+
+    let top = staves.iter().map(|x| x.0[4]).max().unwrap().max(step) + 1;
+    let bottom = staves.iter().map(|x| x.0[0]).min().unwrap().min(step) - 1;
+
+    let key_steps: &[i32] = match key_accidentals > 0 {
+        true => &SHARP_STEPS[..key_accidentals as usize],
+        false => &FLAT_STEPS[..(-key_accidentals) as usize],
+    };
+
+    let mut rows: Vec<String> = vec!();
+
+    for s in (bottom..=top).rev() {
+        let on_line = staves.iter().any(|x| x.0.contains(&s));
+        let mut row: Vec<char> = vec![if on_line { '─' } else { ' ' }; STAFF_WIDTH];
+
+        for (_, label, label_step, offset) in staves.iter() {
+            if s == *label_step {
+                row[0] = *label;
+            }
+
+            for (i, key_step) in key_steps.iter().enumerate() {
+                if s == key_step + offset {
+                    row[KEY_SIG_COLUMN + i * 2] = if key_accidentals > 0 { '♯' } else { '♭' };
+                }
+            }
+        }
+
+        let lines = note_staff.0;
+        let ledger = s % 2 == 0
+            && ((s > lines[4] && s <= step) || (s < lines[0] && s >= step));
+
+        if ledger {
+            for cell in &mut row[NOTE_COLUMN - 1..=NOTE_COLUMN + 1] {
+                *cell = '─';
+            }
+        }
+
+        if s == step {
+            row[NOTE_COLUMN] = '●';
+
+            if let Some(i) = accidental {
+                row[NOTE_COLUMN - 2] = i;
+            }
+        }
+
+        rows.push(row.into_iter().collect());
+    }
+
+    rows
+}
+
+fn candidate_notes(clef: Clef, ledger_lines: bool, accidentals: bool, key_accidentals: i32) -> Vec<u8> {
+    let (low, high) = clef.range(ledger_lines);
+
+    (low..=high)
+        .filter(|x| accidentals || spell(*x, key_accidentals).2.is_none())
+        .collect()
+}
+
+fn practice_sight_reading(input: &mut NoteInput, clef: Clef, ledger_lines: bool, accidentals: bool, key_selection: usize) -> Result<(), Box<dyn Error>> {
+    let (key_name, key_accidentals) = KEY_SIGNATURES[key_selection];
+    let notes = candidate_notes(clef, ledger_lines, accidentals, key_accidentals);
+    let mut session = Session::new("sight_reading", &[
+        ("clef", format!("{:?}", clef)),
+        ("ledger_lines", ledger_lines.to_string()),
+        ("accidentals", accidentals.to_string()),
+        ("key", key_name.to_string()),
+    ]);
+    let mut rng = thread_rng();
+    let mut replay = true;
+
+    while replay {
+        let mut round = Round::default();
+        let mut previous: Option<u8> = None;
+        let mut feedback = String::new();
+        let started = Instant::now();
+        let mut finished = started;
+
+        for i in 0..ROUND_LENGTH {
+            // never ask for the same note twice in a row
+            let note = **notes.iter()
+                .filter(|x| Some(**x) != previous)
+                .collect::<Vec<&u8>>()
+                .choose(&mut rng)
+                .unwrap();
+            previous = Some(note);
+
+            let (name, _, _) = spell(note, key_accidentals);
+            let mut attempt = Attempt::new(name);
+
+            print!("{}{}", clear::All, cursor::Goto(1, 1));
+            println!("{}, note {} of {}\n", key_name, i + 1, ROUND_LENGTH);
+
+            for row in render(clef, key_accidentals, note) {
+                println!("{}", row);
+            }
+
+            println!("\n{}", feedback);
+
+            loop {
+                let (keys, pressed) = input.next_state()?;
+
+                if let Some(key) = keys.last() {
+                    if *key == note {
+                        attempt.finish(pressed);
+                        finished = pressed;
+                        break;
+                    }
+                    else {
+                        attempt.miss(1);
+                        println!("{}{} is not {}{}", color::Fg(color::Red), spell(*key, key_accidentals).0, attempt.item, color::Fg(color::Reset));
+                    }
+                }
+            }
+
+            feedback = format!("{}{} correct{}", color::Fg(color::Green), attempt.item, color::Fg(color::Reset));
+            round.push(attempt);
+        }
+
+        let minutes = finished.saturating_duration_since(started).as_secs_f64() / 60.0;
+
+        print!("{}{}", clear::All, cursor::Goto(1, 1));
+        round.print_summary();
+        if minutes > 0.0 {
+            println!("{:.1} notes per minute\n", ROUND_LENGTH as f64 / minutes);
+        }
+        session.record(&round)?;
+
+        replay = Confirmation::new()
+            .with_text("Would you like to practice again?")
+            .interact()
+            .unwrap();
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const F_SHARP_MAJOR: i32 = 6;
+    const G_FLAT_MAJOR: i32 = -6;
+
+    #[test]
+    fn spells_from_the_key_signature() {
+        assert_eq!(spell(70, -2), ("Bb4".to_string(), 34, None));
+        assert_eq!(spell(66, F_SHARP_MAJOR), ("F#4".to_string(), 31, None));
+        assert_eq!(spell(61, 0), ("C#4".to_string(), 28, Some('♯')));
+        assert_eq!(spell(64, F_SHARP_MAJOR), ("E4".to_string(), 30, Some('♮')));
+    }
+
+    #[test]
+    fn spells_e_sharp_and_c_flat() {
+        // E#4 sits on the E line even though it's the same key as F4
+        assert_eq!(spell(65, F_SHARP_MAJOR), ("E#4".to_string(), 30, None));
+        assert_eq!(spell(77, F_SHARP_MAJOR), ("E#5".to_string(), 37, None));
+        // and Cb is written as a C, so it takes the octave above the B it sounds as
+        assert_eq!(spell(71, G_FLAT_MAJOR), ("Cb5".to_string(), 35, None));
+        assert_eq!(spell(72, G_FLAT_MAJOR), ("C5".to_string(), 35, Some('♮')));
+    }
+
+    #[test]
+    fn leaving_out_accidentals_keeps_the_whole_key() {
+        let notes = candidate_notes(Clef::Treble, false, false, F_SHARP_MAJOR);
+        assert!(notes.contains(&65) && notes.contains(&77));
+        assert!(!notes.contains(&64) && !notes.contains(&76));
+
+        let notes = candidate_notes(Clef::Treble, false, false, G_FLAT_MAJOR);
+        assert!(notes.contains(&71));
+        assert!(!notes.contains(&72));
+    }
+}
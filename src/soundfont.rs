@@ -0,0 +1,320 @@
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// generator operators used to map keys to samples
+const INSTRUMENT: u16 = 41;
+const KEY_RANGE: u16 = 43;
+const COARSE_TUNE: u16 = 51;
+const FINE_TUNE: u16 = 52;
+const SAMPLE_ID: u16 = 53;
+const SAMPLE_MODES: u16 = 54;
+const OVERRIDING_ROOT_KEY: u16 = 58;
+
+const PHDR_SIZE: usize = 38;
+const INST_SIZE: usize = 22;
+const BAG_SIZE: usize = 4;
+const GEN_SIZE: usize = 4;
+const SHDR_SIZE: usize = 46;
+
+// sample types of the two halves of a stereo pair
+const RIGHT_SAMPLE: u16 = 2;
+const LEFT_SAMPLE: u16 = 4;
+
+// one sample and the keys it covers
+#[derive(Debug, Clone)]
+struct Zone {
+    key_range: (u8, u8),
+    root_key: u8,
+    tune_cents: i32,
+    start: usize,
+    end: usize,
+    loop_start: usize,
+    loop_end: usize,
+    // 1 loops for as long as the note sounds, 3 loops until the key is let go
+    loop_mode: u16,
+    sample_rate: u32,
+    // the sample header this zone plays, and the other half of its stereo pair if it has one
+    sample: usize,
+    link: Option<usize>,
+    // the zone playing the other half, which is mixed in as the synth is mono
+    partner: Option<usize>,
+}
+
+// The first preset of an SF2 file, reduced to what's needed to play it back: the 16-bit sample
+// data and which sample each key uses. The file's own envelopes and modulators are ignored in
+// favour of the synth's.
+pub struct SoundFont {
+    samples: Vec<i16>,
+    zones: Vec<Zone>,
+}
+
+fn u16_at(data: &[u8], offset: usize) -> u16 {
+    u16::from(data[offset]) | u16::from(data[offset + 1]) << 8
+}
+
+fn u32_at(data: &[u8], offset: usize) -> u32 {
+    u32::from(u16_at(data, offset)) | u32::from(u16_at(data, offset + 2)) << 16
+}
+
+// RIFF chunk ids and bodies, skipping the pad byte after odd sized chunks
+fn chunks(data: &[u8]) -> Result<Vec<(&[u8], &[u8])>, Box<dyn Error>> {
+    let mut chunks = vec!();
+    let mut offset = 0;
+
+    while offset + 8 <= data.len() {
+        let size = u32_at(data, offset + 4) as usize;
+        let body = data.get(offset + 8..offset + 8 + size).ok_or("truncated SoundFont chunk")?;
+        chunks.push((&data[offset..offset + 4], body));
+        offset += 8 + size + size % 2;
+    }
+
+    Ok(chunks)
+}
+
+fn find<'a>(chunks: &[(&[u8], &'a [u8])], id: &[u8]) -> Result<&'a [u8], Box<dyn Error>> {
+    match chunks.iter().find(|x| x.0 == id) {
+        Some(i) => Ok(i.1),
+        None => Err(format!("SoundFont has no {} chunk", String::from_utf8_lossy(id)).into()),
+    }
+}
+
+fn find_list<'a>(chunks: &[(&[u8], &'a [u8])], list_type: &[u8]) -> Result<&'a [u8], Box<dyn Error>> {
+    match chunks.iter().find(|x| x.0 == b"LIST" && x.1.len() >= 4 && &x.1[..4] == list_type) {
+        Some(i) => Ok(&i.1[4..]),
+        None => Err(format!("SoundFont has no {} list", String::from_utf8_lossy(list_type)).into()),
+    }
+}
+
+// generators of each zone in a bag list, as (operator, amount) pairs
+fn zone_generators(bags: &[u8], gens: &[u8], first_bag: usize, last_bag: usize) -> Vec<Vec<(u16, u16)>> {
+    (first_bag..last_bag)
+        .filter(|x| (x + 1) * BAG_SIZE + 2 <= bags.len())
+        .map(|x| {
+            let first_gen = u16_at(bags, x * BAG_SIZE) as usize;
+            let last_gen = u16_at(bags, (x + 1) * BAG_SIZE) as usize;
+
+            (first_gen..last_gen)
+                .filter(|y| (y + 1) * GEN_SIZE <= gens.len())
+                .map(|y| (u16_at(gens, y * GEN_SIZE), u16_at(gens, y * GEN_SIZE + 2)))
+                .collect()
+        })
+        .collect()
+}
+
+fn generator(generators: &[(u16, u16)], operator: u16) -> Option<u16> {
+    generators.iter().find(|x| x.0 == operator).map(|x| x.1)
+}
+
+fn key_range(generators: &[(u16, u16)]) -> (u8, u8) {
+    match generator(generators, KEY_RANGE) {
+        Some(i) => ((i & 0xFF) as u8, (i >> 8) as u8),
+        None => (0, 127),
+    }
+}
+
+impl SoundFont {
+    pub fn load(path: &Path) -> Result<SoundFont, Box<dyn Error>> {
+        let data = fs::read(path)?;
+
+        if data.len() < 12 || &data[..4] != b"RIFF" || &data[8..12] != b"sfbk" {
+            return Err("not a SoundFont file".into());
+        }
+
+        let top = chunks(&data[12..])?;
+        let sdta = chunks(find_list(&top, b"sdta")?)?;
+        let pdta = chunks(find_list(&top, b"pdta")?)?;
+
+        let samples: Vec<i16> = find(&sdta, b"smpl")?
+            .chunks(2)
+            .filter(|x| x.len() == 2)
+            .map(|x| i16::from_le_bytes([x[0], x[1]]))
+            .collect();
+
+        let phdr = find(&pdta, b"phdr")?;
+        let pbag = find(&pdta, b"pbag")?;
+        let pgen = find(&pdta, b"pgen")?;
+        let inst = find(&pdta, b"inst")?;
+        let ibag = find(&pdta, b"ibag")?;
+        let igen = find(&pdta, b"igen")?;
+        let shdr = find(&pdta, b"shdr")?;
+
+        // the last header of each list only marks where the one before it ends
+        let presets = (phdr.len() / PHDR_SIZE).saturating_sub(1);
+        let preset = (0..presets)
+            .min_by_key(|x| (u16_at(phdr, x * PHDR_SIZE + 22), u16_at(phdr, x * PHDR_SIZE + 20)))
+            .ok_or("SoundFont has no presets")?;
+
+        let mut zones: Vec<Zone> = vec!();
+
+        let preset_zones = zone_generators(pbag, pgen,
+            u16_at(phdr, preset * PHDR_SIZE + 24) as usize,
+            u16_at(phdr, (preset + 1) * PHDR_SIZE + 24) as usize);
+
+        for preset_zone in preset_zones.iter() {
+            let instrument = match generator(preset_zone, INSTRUMENT) {
+                Some(i) if (i as usize + 1) * INST_SIZE + 22 <= inst.len() => i as usize,
+                _ => continue,
+            };
+            let preset_range = key_range(preset_zone);
+
+            let instrument_zones = zone_generators(ibag, igen,
+                u16_at(inst, instrument * INST_SIZE + 20) as usize,
+                u16_at(inst, (instrument + 1) * INST_SIZE + 20) as usize);
+
+            // a first zone without a sample holds defaults for the rest
+            let global: Vec<(u16, u16)> = match instrument_zones.first() {
+                Some(i) if generator(i, SAMPLE_ID).is_none() => i.clone(),
+                _ => vec!(),
+            };
+
+            for instrument_zone in instrument_zones.iter() {
+                let sample_index = match generator(instrument_zone, SAMPLE_ID) {
+                    Some(i) if (i as usize + 1) * SHDR_SIZE <= shdr.len() => i as usize,
+                    _ => continue,
+                };
+                let sample = sample_index * SHDR_SIZE;
+
+                let get = |operator| generator(instrument_zone, operator).or_else(|| generator(&global, operator));
+
+                let (low, high) = match generator(instrument_zone, KEY_RANGE) {
+                    Some(_) => key_range(instrument_zone),
+                    None => key_range(&global),
+                };
+
+                let root_key = match get(OVERRIDING_ROOT_KEY) {
+                    Some(i) if i < 128 => i as u8,
+                    _ => shdr[sample + 40],
+                };
+
+                let tune_cents = get(COARSE_TUNE).map_or(0, |x| i32::from(x as i16)) * 100
+                    + get(FINE_TUNE).map_or(0, |x| i32::from(x as i16))
+                    + i32::from(shdr[sample + 41] as i8);
+
+                zones.push(Zone {
+                    key_range: (low.max(preset_range.0), high.min(preset_range.1)),
+                    root_key,
+                    tune_cents,
+                    start: u32_at(shdr, sample + 20) as usize,
+                    end: (u32_at(shdr, sample + 24) as usize).min(samples.len()),
+                    loop_start: u32_at(shdr, sample + 28) as usize,
+                    loop_end: u32_at(shdr, sample + 32) as usize,
+                    loop_mode: get(SAMPLE_MODES).unwrap_or(0) & 3,
+                    sample_rate: u32_at(shdr, sample + 36).max(1),
+                    sample: sample_index,
+                    link: match u16_at(shdr, sample + 44) {
+                        RIGHT_SAMPLE | LEFT_SAMPLE => Some(u16_at(shdr, sample + 42) as usize),
+                        _ => None,
+                    },
+                    partner: None,
+                });
+            }
+        }
+
+        if zones.is_empty() {
+            return Err("SoundFont's first preset has no samples".into());
+        }
+
+        for i in 0..zones.len() {
+            if let Some(link) = zones[i].link {
+                let key_range = zones[i].key_range;
+                zones[i].partner = zones.iter().position(|x| x.sample == link && x.key_range == key_range);
+            }
+        }
+
+        Ok(SoundFont{samples, zones})
+    }
+
+    pub fn zone(&self, key: u8) -> Option<usize> {
+        self.zones.iter().position(|x| x.key_range.0 <= key && key <= x.key_range.1)
+    }
+
+    // how far to step through the zone's sample per output sample to sound `key`
+    pub fn increment(&self, zone: usize, key: u8, sample_rate: f64) -> f64 {
+        let zone = &self.zones[zone];
+        let cents = (i32::from(key) - i32::from(zone.root_key)) * 100 + zone.tune_cents;
+
+        2f64.powf(f64::from(cents) / 1200.0) * f64::from(zone.sample_rate) / sample_rate
+    }
+
+    fn sample_at(&self, zone: &Zone, position: f64) -> Option<f32> {
+        let index = zone.start + position as usize;
+
+        if index + 1 >= zone.end {
+            return None;
+        }
+
+        let fraction = (position - position.floor()) as f32;
+        Some(f32::from(self.samples[index]) * (1.0 - fraction) + f32::from(self.samples[index + 1]) * fraction)
+    }
+
+    // The sample at `position` frames into the zone, which is then moved on by `increment`. None
+    // once the sample has run out. The halves of a stereo pair are mixed down, assuming they share
+    // a length and loop points as they do in practice.
+    pub fn next(&self, zone: usize, position: &mut f64, increment: f64, held: bool) -> Option<f32> {
+        let zone = &self.zones[zone];
+        let mut value = self.sample_at(zone, *position)?;
+
+        if let Some(partner) = zone.partner {
+            if let Some(i) = self.sample_at(&self.zones[partner], *position) {
+                value = (value + i) / 2.0;
+            }
+        }
+
+        *position += increment;
+
+        let looping = zone.loop_mode == 1 || (zone.loop_mode == 3 && held);
+        if looping && zone.loop_end > zone.loop_start && zone.start + *position as usize >= zone.loop_end {
+            *position -= (zone.loop_end - zone.loop_start) as f64;
+        }
+
+        Some(value / 32768.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], body: &[u8]) -> Vec<u8> {
+        let mut data = id.to_vec();
+        data.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        data.extend_from_slice(body);
+
+        if body.len() % 2 == 1 {
+            data.push(0);
+        }
+
+        data
+    }
+
+    #[test]
+    fn reads_little_endian() {
+        let data = [0x34, 0x12, 0x78, 0x56];
+
+        assert_eq!(u16_at(&data, 0), 0x1234);
+        assert_eq!(u32_at(&data, 0), 0x5678_1234);
+    }
+
+    #[test]
+    fn splits_chunks_past_padding() {
+        let mut data = chunk(b"ifil", &[2, 0, 1]);
+        data.extend(chunk(b"LIST", b"pdta\x01\x02"));
+
+        let parsed = chunks(&data).unwrap();
+
+        assert_eq!(parsed.len(), 2);
+        assert_eq!(find(&parsed, b"ifil").unwrap(), &[2, 0, 1]);
+        assert_eq!(find_list(&parsed, b"pdta").unwrap(), &[1, 2]);
+        assert!(find(&parsed, b"smpl").is_err());
+        assert!(find_list(&parsed, b"sdta").is_err());
+    }
+
+    #[test]
+    fn rejects_truncated_chunks() {
+        let mut data = chunk(b"smpl", &[0; 8]);
+        data.truncate(12);
+
+        assert!(chunks(&data).is_err());
+    }
+}
@@ -1,65 +1,464 @@
 use portaudio as pa;
+use std::error::Error;
 use std::f64::consts::PI;
+use std::fmt;
+use std::path::Path;
+use std::sync::mpsc::{channel, Sender, Receiver};
+use std::thread;
+use std::time::Duration;
+use serde::{Serialize, Deserialize};
 
-pub struct Synth {
-    pub pa: pa::PortAudio,
+use crate::config::Config;
+use crate::soundfont::SoundFont;
+
+const CHANNELS: i32 = 2;
+const FRAMES_PER_BUFFER: u32 = 64;
+const TABLE_SIZE: usize = 2048;
+const SAMPLE_RATE: f64 = 44100.0;
+
+const VOICES: usize = 16;
+// keeps a full chord plus a metronome from clipping
+const MASTER_GAIN: f32 = 0.15;
+
+// relative strength of each harmonic in the piano-ish timbre
+const PIANO_HARMONICS: &'static [f64] = &[1.0, 0.6, 0.35, 0.25, 0.15, 0.1, 0.05, 0.03];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Waveform {
+    Sine,
+    Triangle,
+    Saw,
+    Square,
+    Piano,
 }
 
-impl Synth {
-    pub fn play_note(&self, freq: f64, duration_millis: i32, verbose: bool) -> Result<(), pa::Error> {
-        const CHANNELS: i32 = 2;
-        const FRAMES_PER_BUFFER: u32 = 64;
-        const TABLE_SIZE: usize = 200;
-        const SAMPLE_RATE: f64 = 64000.0;
+pub const WAVEFORMS: &'static [Waveform] = &[Waveform::Sine, Waveform::Triangle, Waveform::Saw, Waveform::Square, Waveform::Piano];
+
+impl Default for Waveform {
+    fn default() -> Waveform {
+        Waveform::Sine
+    }
+}
+
+impl fmt::Display for Waveform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Waveform::Sine => write!(f, "sine"),
+            Waveform::Triangle => write!(f, "triangle"),
+            Waveform::Saw => write!(f, "saw"),
+            Waveform::Square => write!(f, "square"),
+            Waveform::Piano => write!(f, "piano-ish"),
+        }
+    }
+}
+
+impl Waveform {
+    // one cycle, peaking at 1
+    fn table(&self) -> Vec<f32> {
+        let mut table: Vec<f64> = (0..TABLE_SIZE).map(|i| {
+            let x = i as f64 / TABLE_SIZE as f64;
+
+            match self {
+                Waveform::Sine => (x * PI * 2.0).sin(),
+                Waveform::Triangle => 1.0 - 4.0 * (x - 0.5).abs(),
+                Waveform::Saw => 2.0 * x - 1.0,
+                Waveform::Square => if x < 0.5 { 1.0 } else { -1.0 },
+                Waveform::Piano => PIANO_HARMONICS.iter()
+                    .enumerate()
+                    .map(|(n, a)| a * (x * PI * 2.0 * (n + 1) as f64).sin())
+                    .sum(),
+            }
+        }).collect();
+
+        let peak = table.iter().fold(0.0, |a: f64, b| a.max(b.abs()));
+        if peak > 0.0 {
+            for sample in table.iter_mut() {
+                *sample /= peak;
+            }
+        }
+
+        table.into_iter().map(|x| x as f32).collect()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Envelope {
+    pub attack_millis: u32,
+    pub decay_millis: u32,
+    // level held while the key is down, from 0 to 1
+    pub sustain: f32,
+    pub release_millis: u32,
+}
+
+impl Default for Envelope {
+    fn default() -> Envelope {
+        Envelope {
+            attack_millis: 5,
+            decay_millis: 300,
+            sustain: 0.6,
+            release_millis: 200,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+}
+
+#[derive(Debug, Copy, Clone)]
+enum Command {
+    NoteOn { key: u8, velocity: u8 },
+    NoteOff { key: u8 },
+}
+
+#[derive(Copy, Clone)]
+struct Voice {
+    key: u8,
+    active: bool,
+    stage: Stage,
+    phase: f64,
+    increment: f64,
+    velocity: f32,
+    level: f32,
+    release_step: f32,
+    // which of the SoundFont's samples is playing
+    zone: usize,
+    // when the voice was started, so the oldest can be taken if they're all busy
+    started: u64,
+}
+
+impl Default for Voice {
+    fn default() -> Voice {
+        Voice {
+            key: 0,
+            active: false,
+            stage: Stage::Attack,
+            phase: 0.0,
+            increment: 0.0,
+            velocity: 0.0,
+            level: 0.0,
+            release_step: 0.0,
+            zone: 0,
+            started: 0,
+        }
+    }
+}
+
+pub fn key_hz(key: u8) -> f64 {
+    440.0 * 2f64.powf((f64::from(key) - 69.0) / 12.0)
+}
+
+// per-sample change in level for a ramp of `span` taking `millis`
+fn step(span: f32, millis: u32, sample_rate: f64) -> f32 {
+    match millis {
+        0 => span.max(std::f32::EPSILON),
+        _ => span / (f64::from(millis) / 1000.0 * sample_rate) as f32,
+    }
+}
+
+enum Sound {
+    Wavetable(Vec<f32>),
+    Samples(SoundFont),
+}
+
+// The SoundFont from the settings if there is one, otherwise the chosen waveform
+fn load_sound(config: &Config) -> Sound {
+    if let Some(path) = &config.soundfont {
+        match SoundFont::load(Path::new(path)) {
+            Ok(i) => return Sound::Samples(i),
+            Err(e) => println!("Couldn't load SoundFont '{}': {}. Using the {} synth instead", path, e, config.waveform),
+        }
+    }
+
+    Sound::Wavetable(config.waveform.table())
+}
+
+// The voices and their envelopes, independent of where the samples end up.
+struct Engine {
+    sound: Sound,
+    envelope: Envelope,
+    sample_rate: f64,
+    voices: [Voice; VOICES],
+    notes_started: u64,
+}
+
+impl Engine {
+    fn new(sound: Sound, envelope: Envelope, sample_rate: f64) -> Engine {
+        Engine {
+            sound,
+            envelope,
+            sample_rate,
+            voices: [Voice::default(); VOICES],
+            notes_started: 0,
+        }
+    }
+
+    fn command(&mut self, command: Command) {
+        match command {
+            Command::NoteOn { key, velocity } => {
+                let (zone, increment) = match &self.sound {
+                    Sound::Wavetable(_) => (0, (TABLE_SIZE as f64 / self.sample_rate) * key_hz(key)),
+                    Sound::Samples(font) => match font.zone(key) {
+                        Some(i) => (i, font.increment(i, key, self.sample_rate)),
+                        // outside the SoundFont's range
+                        None => return,
+                    },
+                };
+
+                // retrigger the same key, or take a free voice, or steal the oldest
+                let voices = &self.voices;
+                let index = voices.iter().position(|x| x.active && x.key == key)
+                    .or_else(|| voices.iter().position(|x| !x.active))
+                    .unwrap_or_else(|| (0..VOICES).min_by_key(|x| voices[*x].started).unwrap_or(0));
+
+                let voice = &mut self.voices[index];
+                if !voice.active {
+                    voice.level = 0.0;
+                    voice.phase = 0.0;
+                }
+                // a sample is struck again from its start
+                if let Sound::Samples(_) = self.sound {
+                    voice.phase = 0.0;
+                }
+                voice.key = key;
+                voice.active = true;
+                voice.stage = Stage::Attack;
+                voice.zone = zone;
+                voice.increment = increment;
+                voice.velocity = f32::from(velocity) / 127.0;
+                voice.started = self.notes_started;
+                self.notes_started += 1;
+            },
+            Command::NoteOff { key } => {
+                let release_millis = self.envelope.release_millis;
+                let sample_rate = self.sample_rate;
+
+                for voice in self.voices.iter_mut().filter(|x| x.active && x.key == key && x.stage != Stage::Release) {
+                    voice.stage = Stage::Release;
+                    voice.release_step = step(voice.level, release_millis, sample_rate);
+                }
+            },
+        }
+    }
 
-        // Initialise sinusoidal wavetable.
-        let mut sine = [0.0; TABLE_SIZE];
+    fn next_sample(&mut self) -> f32 {
+        let attack_step = step(1.0, self.envelope.attack_millis, self.sample_rate);
+        let decay_step = step(1.0 - self.envelope.sustain, self.envelope.decay_millis, self.sample_rate);
+        let sustain = self.envelope.sustain;
+        let sound = &self.sound;
+        let mut sample = 0.0;
 
-        for i in 0..TABLE_SIZE {
-            sine[i] = (i as f64 / TABLE_SIZE as f64 * PI * 2.0).sin() as f32;
+        for voice in self.voices.iter_mut().filter(|x| x.active) {
+            match voice.stage {
+                Stage::Attack => {
+                    voice.level += attack_step;
+                    if voice.level >= 1.0 {
+                        voice.level = 1.0;
+                        voice.stage = Stage::Decay;
+                    }
+                },
+                Stage::Decay => {
+                    voice.level -= decay_step;
+                    if voice.level <= sustain {
+                        voice.level = sustain;
+                        voice.stage = Stage::Sustain;
+                    }
+                },
+                Stage::Sustain => {},
+                Stage::Release => {
+                    voice.level -= voice.release_step;
+                    if voice.level <= 0.0 {
+                        voice.active = false;
+                        continue;
+                    }
+                },
+            }
+
+            let value = match sound {
+                Sound::Wavetable(table) => {
+                    let value = table[voice.phase as usize];
+
+                    voice.phase += voice.increment;
+                    if voice.phase as usize >= TABLE_SIZE {
+                        voice.phase -= TABLE_SIZE as f64;
+                    }
+
+                    value
+                },
+                Sound::Samples(font) => match font.next(voice.zone, &mut voice.phase, voice.increment, voice.stage != Stage::Release) {
+                    Some(i) => i,
+                    None => {
+                        voice.active = false;
+                        continue;
+                    }
+                },
+            };
+
+            sample += value * voice.level * voice.velocity;
+        }
+
+        (sample * MASTER_GAIN).max(-1.0).min(1.0)
+    }
+}
+
+// Plays into memory instead of the audio device, with the same sound and envelope as the live
+// Synth. Time only moves on when rest() is called, so a recording takes as long to make as it
+// takes to compute rather than to listen to.
+pub struct Recording {
+    engine: Engine,
+    samples: Vec<f32>,
+}
+
+impl Recording {
+    pub fn new() -> Result<Recording, Box<dyn Error>> {
+        Ok(Recording::with_config(&Config::load()?))
+    }
+
+    pub fn with_config(config: &Config) -> Recording {
+        Recording {
+            engine: Engine::new(load_sound(config), config.envelope, SAMPLE_RATE),
+            samples: vec!(),
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE as u32
+    }
+
+    pub fn note_on(&mut self, key: u8, velocity: u8) {
+        self.engine.command(Command::NoteOn{key, velocity});
+    }
+
+    pub fn note_off(&mut self, key: u8) {
+        self.engine.command(Command::NoteOff{key});
+    }
+
+    // renders `millis` of whatever is sounding, which is silence if nothing is
+    pub fn rest(&mut self, millis: u64) {
+        let frames = (millis as f64 / 1000.0 * SAMPLE_RATE) as usize;
+
+        for _ in 0..frames {
+            let sample = self.engine.next_sample();
+            self.samples.push(sample);
+        }
+    }
+
+    // the offline counterpart of SynthHandle::play
+    pub fn play(&mut self, keys: &[u8], duration_millis: u64) {
+        for key in keys {
+            self.note_on(*key, 100);
+        }
+
+        self.rest(duration_millis);
+
+        for key in keys {
+            self.note_off(*key);
+        }
+    }
+
+    pub fn millis(&self) -> u64 {
+        (self.samples.len() as f64 / SAMPLE_RATE * 1000.0) as u64
+    }
+
+    pub fn samples(&self) -> &[f32] {
+        &self.samples
+    }
+}
+
+// Sends note commands to a Synth from any thread.
+#[derive(Clone)]
+pub struct SynthHandle {
+    commands: Sender<Command>,
+}
+
+impl SynthHandle {
+    pub fn note_on(&self, key: u8, velocity: u8) {
+        // the stream only goes away with the Synth, at which point nothing needs to sound
+        let _ = self.commands.send(Command::NoteOn{key, velocity});
+    }
+
+    pub fn note_off(&self, key: u8) {
+        let _ = self.commands.send(Command::NoteOff{key});
+    }
+
+    // sounds `keys` together for `duration_millis`, blocking until they're let go
+    pub fn play(&self, keys: &[u8], duration_millis: u64) {
+        for key in keys {
+            self.note_on(*key, 100);
         }
 
-        let mut settings = self.pa.default_output_stream_settings(CHANNELS, SAMPLE_RATE, FRAMES_PER_BUFFER)?;
-        // we won't output out of range samples so don't bother clipping them.
+        thread::sleep(Duration::from_millis(duration_millis));
+
+        for key in keys {
+            self.note_off(*key);
+        }
+    }
+}
+
+// A polyphonic synth on a single output stream that stays open for as long as the Synth lives.
+// Notes are started and stopped through a SynthHandle, so exercises, the metronome and anything
+// else can all sound at once without blocking each other. The waveform and envelope come from
+// the settings.
+pub struct Synth {
+    // the stream has to be closed before PortAudio is dropped
+    stream: pa::Stream<pa::NonBlocking, pa::Output<f32>>,
+    _pa: pa::PortAudio,
+    handle: SynthHandle,
+}
+
+impl Synth {
+    pub fn new() -> Result<Synth, Box<dyn Error>> {
+        let config = Config::load()?;
+        let pa = pa::PortAudio::new()?;
+
+        let mut settings = pa.default_output_stream_settings(CHANNELS, SAMPLE_RATE, FRAMES_PER_BUFFER)?;
+        // the mix is clamped in the engine, so don't bother clipping it again.
         settings.flags = pa::stream_flags::CLIP_OFF;
 
+        let (sender, commands): (Sender<Command>, Receiver<Command>) = channel();
+        let mut engine = Engine::new(load_sound(&config), config.envelope, SAMPLE_RATE);
+
         // This routine will be called by the PortAudio engine when audio is needed. It may called at
         // interrupt level on some machines so don't do anything that could mess up the system like
         // dynamic resource allocation or IO.
-        let increment: f64 = (TABLE_SIZE as f64 / SAMPLE_RATE) * freq;
-        let mut left_phase = 0.0;
-        let mut right_phase = 0.0;
-
         let callback = move |pa::OutputStreamCallbackArgs { buffer, frames, .. }| {
+            while let Ok(command) = commands.try_recv() {
+                engine.command(command);
+            }
+
             let mut idx = 0;
             for _ in 0..frames {
-                buffer[idx] = sine[left_phase as usize];
-                buffer[idx + 1] = sine[right_phase as usize];
-                left_phase += increment;
-                if left_phase as usize >= TABLE_SIZE {
-                    left_phase -= TABLE_SIZE as f64;
-                }
-                right_phase += increment;
-                if right_phase as usize >= TABLE_SIZE {
-                    right_phase -= TABLE_SIZE as f64;
-                }
+                let sample = engine.next_sample();
+                buffer[idx] = sample;
+                buffer[idx + 1] = sample;
                 idx += 2;
             }
             pa::Continue
         };
 
-        let mut stream = self.pa.open_non_blocking_stream(settings, callback)?;
-
+        let mut stream = pa.open_non_blocking_stream(settings, callback)?;
         stream.start()?;
 
-        if verbose {
-            println!("Play {}Hz for {} milliseconds.", freq, duration_millis);
-        }
+        Ok(Synth {
+            stream,
+            _pa: pa,
+            handle: SynthHandle{commands: sender},
+        })
+    }
 
-        self.pa.sleep(duration_millis);
+    pub fn handle(&self) -> SynthHandle {
+        self.handle.clone()
+    }
 
-        stream.stop()?;
-        stream.close()?;
+    pub fn close(mut self) -> Result<(), pa::Error> {
+        self.stream.stop()?;
+        self.stream.close()?;
 
         Ok(())
     }
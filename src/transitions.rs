@@ -0,0 +1,193 @@
+use std::error::Error;
+use std::time::{Duration, Instant};
+use dialoguer::{theme::ColorfulTheme, Select, Confirmation};
+use termion::color;
+
+use crate::midi::{midi_connect, NoteInput};
+use crate::repetition::Deck;
+use crate::grading::{Attempt, Round};
+use crate::history::Session;
+use crate::chords::{select_chord_type, select_hand, chord_key, identify_chord, chord_matches, wrong_notes};
+use crate::utils::{
+    music::note_index,
+    types::{Hand, ChordType, Chord},
+    constants::NOTE_NAMES,
+};
+
+const ROUND_LENGTH: usize = 12;
+const SLOW_TRANSITION_MILLIS: u64 = 1000;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Voicing {
+    RootPosition,
+    ClosestInversion,
+}
+
+pub fn practice_transitions_launcher() -> Result<(), Box<dyn Error>> {
+    let from_type = select_chord_type("Pick the first chord variant");
+    let to_type = select_chord_type("Pick the second chord variant");
+
+    let voicing_selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("How should the second chord be voiced?")
+        .items(&["root position", "closest inversion"])
+        .interact()
+        .unwrap();
+
+    let voicing = match voicing_selection {
+        0 => Voicing::RootPosition,
+        _ => Voicing::ClosestInversion,
+    };
+
+    let hand = select_hand();
+
+    match midi_connect() {
+        Err(e) => Err(e),
+        Ok(mut conn_in) => {
+            let result = practice_transitions(&mut conn_in, from_type, to_type, voicing, hand);
+            conn_in.close();
+            result
+        }
+    }
+}
+
+fn transition_key(from: &Chord, to: &Chord, hand: Hand) -> String {
+    format!("transition:{}->{}", chord_key(from, hand), chord_key(to, hand))
+}
+
+fn generate_transitions(from_type: ChordType, to_type: ChordType, voicing: Voicing, hand: Hand, deck: &Deck) -> Vec<(Chord, Chord)> {
+    let mut transitions: Vec<(Chord, Chord)> = vec!();
+
+    for from_root in NOTE_NAMES.iter() {
+        for to_root in NOTE_NAMES.iter() {
+            if from_root == to_root && from_type == to_type {
+                continue;
+            }
+
+            let from = Chord{root: from_root[0].to_string(), chord_type: from_type, inversion: 0, octave: None};
+
+            let inversion = match voicing {
+                Voicing::RootPosition => 0,
+                Voicing::ClosestInversion => closest_inversion(&from, to_root[0], to_type),
+            };
+
+            let to = Chord{root: to_root[0].to_string(), chord_type: to_type, inversion, octave: None};
+
+            transitions.push((from, to));
+        }
+    }
+
+    deck.sort(&mut transitions, |x| transition_key(&x.0, &x.1, hand));
+    transitions.truncate(ROUND_LENGTH);
+    transitions
+}
+
+// semitones above C, lowest `inversion` notes raised an octave
+pub fn voicing_of(root: &str, chord_type: ChordType, inversion: usize) -> Vec<i32> {
+    let root_index = note_index(root).unwrap_or(0) as i32;
+    let mut notes: Vec<i32> = chord_type.positions().iter().map(|x| root_index + *x as i32).collect();
+
+    for i in 0..inversion.min(notes.len()) {
+        notes[i] += 12;
+    }
+
+    notes.sort();
+    notes
+}
+
+// total distance each voice has to travel, with the target shifted to whichever octave is nearest
+fn movement(from: &[i32], to: &[i32]) -> i32 {
+    [-12, 0, 12].iter()
+        .map(|shift| to.iter()
+            .map(|x| from.iter().map(|y| (x + shift - y).abs()).min().unwrap_or(0))
+            .sum())
+        .min()
+        .unwrap_or(0)
+}
+
+pub fn closest_inversion(from: &Chord, to_root: &str, to_type: ChordType) -> usize {
+    let from_notes = voicing_of(&from.root, from.chord_type, from.inversion);
+
+    (0..to_type.positions().len())
+        .min_by_key(|x| movement(&from_notes, &voicing_of(to_root, to_type, *x)))
+        .unwrap_or(0)
+}
+
+// blocks until any held key is let go
+fn wait_for_release(input: &mut NoteInput) -> Result<Instant, Box<dyn Error>> {
+    loop {
+        let event = input.wait_event()?;
+
+        if !event.down {
+            return Ok(event.time);
+        }
+    }
+}
+
+fn practice_transitions(input: &mut NoteInput, from_type: ChordType, to_type: ChordType, voicing: Voicing, hand: Hand) -> Result<(), Box<dyn Error>> {
+    let mut deck = Deck::load()?;
+    let mut session = Session::new("transitions", &[
+        ("from_type", format!("{:?}", from_type)),
+        ("to_type", format!("{:?}", to_type)),
+        ("voicing", format!("{:?}", voicing)),
+        ("hand", format!("{:?}", hand)),
+    ]);
+    let mut replay = true;
+
+    while replay {
+        let transitions = generate_transitions(from_type, to_type, voicing, hand, &deck);
+        let mut round = Round::default();
+
+        for (from, to) in transitions.iter() {
+            println!("Play {}, then change to {}, {}", from, to, hand);
+
+            loop {
+                if let Some(i) = identify_chord(&input.next_state()?.0) {
+                    if chord_matches(from, hand, &i) {
+                        break;
+                    }
+                }
+            }
+
+            println!("Now change to {}", to);
+
+            // timing starts when the first chord is let go, not when it was prompted
+            let mut attempt = Attempt::new(format!("{} -> {}, {}", from, to, hand));
+            attempt.prompted = wait_for_release(input)?;
+
+            loop {
+                let (keys, pressed) = input.next_state()?;
+
+                // partially released shapes on the way between chords aren't mistakes
+                if keys.len() < to.chord_type.positions().len() {
+                    continue;
+                }
+
+                match identify_chord(&keys) {
+                    Some(i) if chord_matches(to, hand, &i) => {
+                        println!("{}Correct! {:.2}s{}", color::Fg(color::Green), pressed.saturating_duration_since(attempt.prompted).as_secs_f64(), color::Fg(color::Reset));
+                        attempt.finish(pressed);
+                        break;
+                    },
+                    _ => {
+                        attempt.miss(wrong_notes(to, &keys));
+                        println!("{}Try again: {}, {}{}", color::Fg(color::Red), to, hand, color::Fg(color::Reset));
+                    }
+                }
+            }
+
+            deck.review(&transition_key(from, to, hand), attempt.quality(Duration::from_millis(SLOW_TRANSITION_MILLIS)));
+            deck.save()?;
+            round.push(attempt);
+        }
+
+        round.print_summary();
+        session.record(&round)?;
+
+        replay = Confirmation::new()
+            .with_text("Would you like to practice again?")
+            .interact()
+            .unwrap();
+    }
+
+    Ok(())
+}
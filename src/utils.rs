@@ -2,36 +2,56 @@ pub mod music {
     use super::constants::{NOTE_NAMES, MIDI_START_INDEX};
 
     pub fn get_octave(key_index: u8) -> Option<u8> {
-        let note_index = key_index - MIDI_START_INDEX;
-
-        Some(note_index / NOTE_NAMES.len() as u8)
+        // keys below the first octave don't have one
+        key_index.checked_sub(MIDI_START_INDEX).map(|x| x / NOTE_NAMES.len() as u8)
     }
 
     pub fn get_note_name(key_index: u8) -> String {
-        let note_index = key_index - MIDI_START_INDEX;
+        NOTE_NAMES[pitch_class(key_index)][0].to_string()
+    }
 
-        NOTE_NAMES[note_index as usize % NOTE_NAMES.len()][0].to_string()
+    // scientific pitch notation, so 60 is C4
+    pub fn note_label(key_index: u8) -> String {
+        format!("{}{}", NOTE_NAMES[key_index as usize % NOTE_NAMES.len()][0], i32::from(key_index) / 12 - 1)
     }
 
-    pub fn note_matches(key_index: u8, note: &str) -> bool {
-        let note_index = key_index - MIDI_START_INDEX;
+    pub fn pitch_class(key_index: u8) -> usize {
+        // MIDI_START_INDEX is a whole number of octaves, so this is the same for every key
+        key_index as usize % NOTE_NAMES.len()
+    }
+
+    pub fn note_index(note: &str) -> Option<usize> {
+        NOTE_NAMES.iter().position(|x| x.contains(&note))
+    }
 
-        return NOTE_NAMES[note_index as usize % NOTE_NAMES.len()].contains(&note);
+    pub fn note_matches(key_index: u8, note: &str) -> bool {
+        return NOTE_NAMES[pitch_class(key_index)].contains(&note);
     }
 }
 
-pub mod mutex {
-    use std::sync::Mutex;
-    use std::time::Instant;
+pub mod paths {
+    use std::error::Error;
+    use std::path::PathBuf;
 
-    lazy_static! {
-        pub static ref KEYS_DOWN: Mutex<Vec<u8>> = Mutex::new(vec![]);
-        pub static ref LAST_KEY_PRESS: Mutex<Option<Instant>> = Mutex::new(None);
+    pub fn data_dir() -> Result<PathBuf, Box<dyn Error>> {
+        match dirs::data_dir() {
+            Some(dir) => Ok(dir.join("rustykeys")),
+            None => Err("could not locate a data directory".into()),
+        }
+    }
+
+    pub fn config_dir() -> Result<PathBuf, Box<dyn Error>> {
+        match dirs::config_dir() {
+            Some(dir) => Ok(dir.join("rustykeys")),
+            None => Err("could not locate a config directory".into()),
+        }
     }
 }
 
 pub mod constants {
     pub const DEBOUNCE_MILLIS: u64 = 100;
+    // typing a chord takes far longer than striking one
+    pub const KEYBOARD_DEBOUNCE_MILLIS: u64 = 400;
     pub const MIDI_START_INDEX: u8 = 24;
 
 
@@ -55,7 +75,7 @@ pub mod types {
     use ordinal::Ordinal;
     use num_derive::FromPrimitive;
 
-    #[derive(Copy, Clone, PartialEq, Eq)]
+    #[derive(Debug, Copy, Clone, PartialEq, Eq)]
     pub enum Hand {
         Left,
         Right,
@@ -142,6 +162,42 @@ pub mod types {
                ChordType::SusSix => "sus6".to_string(),
             }
         }
+
+        // semitones above the root, in root position
+        pub fn positions(&self) -> Vec<u8> {
+           match *self {
+               ChordType::Major => vec![0, 4, 7],
+               ChordType::Minor => vec![0, 3, 7],
+               ChordType::Diminished => vec![0, 3, 6],
+               ChordType::MajorSeventh => vec![0, 4, 7, 11],
+               ChordType::MinorSeventh => vec![0, 3, 7, 10],
+               ChordType::DominantSeventh => vec![0, 4, 7, 10],
+               ChordType::Augmented => vec![0, 4, 8],
+               ChordType::SusTwo => vec![0, 2, 7],
+               ChordType::SusFour => vec![0, 5, 7],
+               ChordType::SevenSusTwo => vec![0, 2, 7, 10],
+               ChordType::SevenSusFour => vec![0, 5, 7, 10],
+               ChordType::SusSix => vec![0, 4, 7, 9],
+            }
+        }
+
+        pub fn from_positions(positions: &[u8]) -> Option<ChordType> {
+            match positions {
+                [0, 4, 7] => Some(ChordType::Major),
+                [0, 3, 7] => Some(ChordType::Minor),
+                [0, 3, 6] => Some(ChordType::Diminished),
+                [0, 4, 7, 11] => Some(ChordType::MajorSeventh),
+                [0, 3, 7, 10] => Some(ChordType::MinorSeventh),
+                [0, 4, 7, 10] => Some(ChordType::DominantSeventh),
+                [0, 4, 8] => Some(ChordType::Augmented),
+                [0, 2, 7] => Some(ChordType::SusTwo),
+                [0, 5, 7] => Some(ChordType::SusFour),
+                [0, 2, 7, 10] => Some(ChordType::SevenSusTwo),
+                [0, 5, 7, 10] => Some(ChordType::SevenSusFour),
+                [0, 4, 7, 9] => Some(ChordType::SusSix),
+                _ => None,
+            }
+        }
     }
 
     #[derive(Debug)]
@@ -170,7 +226,8 @@ pub mod types {
 
     impl PartialEq for Chord {
         fn eq(&self, other: &Self) -> bool {
-            if self.root != other.root
+            // the same note can be spelled as a sharp or a flat
+            if super::music::note_index(&self.root) != super::music::note_index(&other.root)
                 || self.chord_type != other.chord_type
                 || self.inversion != other.inversion
                 || (self.octave != None && other.octave != None && self.octave != other.octave) {